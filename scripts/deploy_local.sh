@@ -49,11 +49,21 @@ docker run --rm -v "$(pwd)":/code \
 --platform linux/amd64 \
 cosmwasm/rust-optimizer:0.12.3
 
+# Compile the reference fast oracle, the optimizer only builds default features
+docker run --rm -v "$(pwd)":/code \
+--mount type=volume,source="$(basename "$(pwd)")_cache",target=/code/target \
+--mount type=volume,source=registry_cache,target=/usr/local/cargo/registry \
+--platform linux/amd64 \
+--entrypoint /bin/sh \
+cosmwasm/rust-optimizer:0.12.3 \
+-c 'RUSTFLAGS="-C link-arg=-s" cargo build --release --lib --target wasm32-unknown-unknown --locked --features reference-oracle && cp target/wasm32-unknown-unknown/release/prediction_game.wasm artifacts/reference_oracle.wasm'
+
 # Copy binaries to docker container
 docker cp artifacts/wasmswap.wasm cosmwasm:/wasmswap.wasm
 docker cp scripts/cw20_base.wasm cosmwasm:/cw20_base.wasm
 docker cp scripts/stake_cw20.wasm cosmwasm:/stake_cw20.wasm
 docker cp scripts/stake_cw20_external_rewards.wasm cosmwasm:/stake_cw20_external_rewards.wasm
+docker cp artifacts/reference_oracle.wasm cosmwasm:/reference_oracle.wasm
 
 # Sleep while waiting for chain to post genesis block
 sleep 10
@@ -330,6 +340,25 @@ REWARD_2_FUND_MSG='{
 echo $REWARD_2_FUND_MSG
 $BINARY tx wasm execute $CW20_CONTRACT "$REWARD_2_FUND_MSG" --from validator $TXFLAG
 
+#### REFERENCE ORACLE ####
+# Upload reference oracle contract code
+echo xxxxxxxxx | $BINARY tx wasm store "/reference_oracle.wasm" --from validator $TXFLAG
+ORACLE_CODE=$($BINARY q wasm list-code --reverse --output json $QFLAG | jq -r '.code_infos[0].code_id')
+echo $ORACLE_CODE
+
+# Instantiate reference oracle, the validator feeds prices
+ORACLE_INIT='{
+    "owner": "'$1'",
+    "feeders": ["'"$($BINARY keys show validator -a)"'"],
+    "price": "1000000"
+}'
+echo "$ORACLE_INIT"
+echo xxxxxxxxx | $BINARY tx wasm instantiate $ORACLE_CODE "$ORACLE_INIT" --from "validator" --label "reference_oracle" $TXFLAG
+ORACLE_CONTRACT=$($BINARY q wasm list-contract-by-code $ORACLE_CODE --output json $QFLAG | jq -r '.contracts[-1]')
+
+echo xxxxxxxxx | $BINARY tx wasm execute $ORACLE_CONTRACT '{"update":{"price":"1010000"}}' --from validator $TXFLAG
+$BINARY query wasm contract-state smart $ORACLE_CONTRACT '{"price_data":{}}' --output json $QFLAG
+
 
 echo "CRAB cw20 contract 1"
 echo $CW20_CONTRACT
@@ -359,4 +388,5 @@ echo "POOD Staking contract 1"
 echo $STAKING_4_CONTRACT
 echo "CRAB <> DAO Swap contract 5"
 echo $SWAP_5_CONTRACT
-
+echo "Reference oracle contract"
+echo $ORACLE_CONTRACT
@@ -1,6 +1,6 @@
 use thiserror::Error;
 
-use cosmwasm_std::{StdError, Uint128};
+use cosmwasm_std::{Decimal, StdError, Uint128};
 
 #[derive(Error, Debug, PartialEq)]
 pub enum ContractError {
@@ -36,4 +36,37 @@ pub enum ContractError {
 
     #[error("It is less than the minimum bet amount")]
     InsufficientFundsForBet {},
+
+    #[error("A single bet can not be more than {maximum}")]
+    ExceedMaximumBet { maximum: Uint128 },
+
+    #[error("You can not stake more than {maximum} in one round")]
+    ExceedPlayerRoundMaximum { maximum: Uint128 },
+
+    #[error("This bet would make one side more than {max_ratio} times the other")]
+    ExceedPoolRatio { max_ratio: Decimal },
+
+    #[error("This round's winnings have already expired")]
+    RoundExpired {},
+
+    #[error("This round can still be claimed")]
+    ClaimWindowOpen {},
+
+    #[error("You have no bet in this round")]
+    BetNotFound {},
+
+    #[error("This round is about to lock, you can not cancel or switch your bet")]
+    CancelWindowClosed {},
+
+    #[error("You have no strategy")]
+    StrategyNotFound {},
+
+    #[error("You already registered a referrer")]
+    ReferrerAlreadySet {},
+
+    #[error("The operator approval has expired")]
+    OperatorExpired {},
+
+    #[error("The operator can only bet {remaining} more for this player")]
+    ExceedOperatorSpendLimit { remaining: Uint128 },
 }
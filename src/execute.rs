@@ -1,27 +1,41 @@
 use crate::error::ContractError;
+use crate::fees::{player_fee_tier, record_volume, remove_volume};
 use crate::msg::{
-    Config, Direction, ExecuteMsg, FastOracleQueryMsg, FinishedRound, InstantiateMsg, LiveRound,
-    MigrateMsg, NextRound, QueryMsg,
+    Config, Direction, ExecuteMsg, ExpiryDestination, FeeDistribution, FeeMode, FinishedRound,
+    InstantiateMsg, LiveRound, MigrateMsg, NextRound, OracleSource, PriceObservation, PriceSource,
+    PushSettlement, StakingRewardsReceiveMsg, StrategyKind, TiePolicy,
+};
+use crate::oracle::{
+    aggregate_price, observe_prices, query_price, sample_twap, AggregatedPrice, PRICE_DECIMALS,
+};
+use crate::settlement::{
+    compute_bet_payout, record_payout, resolve_referrals, round_expires_at, settle_player,
 };
-use crate::query::query_my_games_without_limit;
 use crate::state::{
-    bet_info_key, bet_info_storage, BetInfo, ACCUMULATED_FEE, CONFIG, IS_HAULTED, LIVE_ROUND,
-    NEXT_ROUND, NEXT_ROUND_ID, ROUNDS, TOTAL_VOLUME,
+    bet_info_key, bet_info_storage, remove_bet, BetInfo, OperatorApproval, SettlementCursor,
+    Strategy, StrategyCursor, ACCUMULATED_FEE, BALANCES, BALANCE_BETS, CONFIG, DUST, IS_HAULTED,
+    LEGACY_CONFIG, LIVE_ROUND, NEXT_ROUND, NEXT_ROUND_ID, OPERATORS, PENDING_REFERRALS,
+    PENDING_SETTLEMENTS, REFERRAL_COUNTS, REFERRAL_EARNINGS, REFERRERS, ROLLOVER_POOL, ROUNDS,
+    STRATEGIES, STRATEGY_CURSOR, TOTAL_VOLUME, UNTRACKED_ROUNDS,
 };
 
-#[cfg(not(feature = "library"))]
+#[cfg(not(any(feature = "library", feature = "reference-oracle")))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    to_binary, Addr, Coin, CosmosMsg, Deps, DepsMut, Env, Event, MessageInfo, QueryRequest,
-    Response, StdError, StdResult, Uint128, WasmMsg, WasmQuery,
+    to_binary, Addr, Binary, Coin, CosmosMsg, Deps, DepsMut, Empty, Env, Event, MessageInfo, Order,
+    Response, StdError, StdResult, Storage, Timestamp, Uint128, WasmMsg,
 };
 use cw20::Cw20ExecuteMsg;
+use cw_storage_plus::Bound;
 
 const CONTRACT_NAME: &str = "price_prediction";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const FEE_PRECISION: u128 = 100u128;
 
-#[cfg_attr(not(feature = "library"), entry_point)]
+#[cfg_attr(
+    not(any(feature = "library", feature = "reference-oracle")),
+    entry_point
+)]
 pub fn instantiate(
     deps: DepsMut,
     _env: Env,
@@ -30,131 +44,1136 @@ pub fn instantiate(
 ) -> Result<Response, ContractError> {
     cw2::set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
     /* Validate addresses */
-    deps.api
-        .addr_validate(msg.config.fast_oracle_addr.as_ref())?;
+    deps.api.addr_validate(msg.config.treasury_addr.as_ref())?;
+    if let Some(staking_rewards_addr) = &msg.config.staking_rewards_addr {
+        deps.api.addr_validate(staking_rewards_addr.as_ref())?;
+    }
+    if let Some(staking_addr) = &msg.config.staking_addr {
+        deps.api.addr_validate(staking_addr.as_ref())?;
+    }
+    assert_valid_fee_distribution(&msg.config.fee_distribution)?;
+    assert_valid_cancel_penalty(&msg.config)?;
+    assert_valid_oracles(deps.as_ref(), &msg.config)?;
 
     CONFIG.save(deps.storage, &msg.config)?;
     NEXT_ROUND_ID.save(deps.storage, &0u128)?;
     ACCUMULATED_FEE.save(deps.storage, &0u128)?;
     IS_HAULTED.save(deps.storage, &false)?;
     TOTAL_VOLUME.save(deps.storage, &Uint128::zero())?;
+    ROLLOVER_POOL.save(deps.storage, &Uint128::zero())?;
+    DUST.save(deps.storage, &Uint128::zero())?;
 
     Ok(Response::new())
 }
 
-#[cfg_attr(not(feature = "library"), entry_point)]
-pub fn migrate(deps: DepsMut, _env: Env, MigrateMsg {}: MigrateMsg) -> StdResult<Response> {
+#[cfg_attr(
+    not(any(feature = "library", feature = "reference-oracle")),
+    entry_point
+)]
+pub fn migrate(mut deps: DepsMut, env: Env, MigrateMsg {}: MigrateMsg) -> StdResult<Response> {
     let version = cw2::get_contract_version(deps.storage)?;
     if version.contract != CONTRACT_NAME {
         return Err(StdError::generic_err("Can only upgrade from same type"));
     }
+    /* Only the first version went without a rollover pool */
+    if ROLLOVER_POOL.may_load(deps.storage)?.is_none() {
+        migrate_legacy_layout(deps.branch(), &env)?;
+    }
 
     cw2::set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
     Ok(Response::default())
 }
 
-#[cfg_attr(not(feature = "library"), entry_point)]
-pub fn execute(
+/**
+ * Brings a deployment of the first version to the current layout. New settings keep the
+ * game as it was, the admin opts into the rest with UpdateConfig.
+ */
+fn migrate_legacy_layout(deps: DepsMut, env: &Env) -> StdResult<()> {
+    let legacy = LEGACY_CONFIG.load(deps.storage)?;
+    /* The first version kept every fee, they go to whoever could withdraw them */
+    let treasury_addr = deps
+        .querier
+        .query_wasm_contract_info(env.contract.address.clone())?
+        .admin
+        .ok_or_else(|| StdError::generic_err("Can't migrate a contract without an admin"))?;
+
+    let config = Config {
+        next_round_seconds: legacy.next_round_seconds,
+        /* Prices were used as the oracle reported them, taking them as already normalized
+         * keeps them that way */
+        oracles: vec![OracleSource::FastOracle {
+            addr: legacy.fast_oracle_addr,
+            decimals: PRICE_DECIMALS,
+        }],
+        oracle_quorum: 1,
+        max_oracle_deviation: Uint128::zero(),
+        fallback_oracles: vec![],
+        minimum_bet: legacy.minimum_bet,
+        burn_fee: legacy.burn_fee,
+        gaming_fee: legacy.gaming_fee,
+        token_addr: legacy.token_addr,
+        cancel_penalty: Uint128::zero(),
+        /* Bets couldn't be cancelled, a buffer of a whole round keeps it that way */
+        cancel_buffer_seconds: legacy.next_round_seconds,
+        maximum_bet: None,
+        maximum_player_round_bet: None,
+        max_pool_ratio: None,
+        bet_lock_buffer_seconds: Uint128::zero(),
+        tie_policy: TiePolicy::Refund,
+        treasury_addr: deps.api.addr_validate(&treasury_addr)?,
+        claim_window_seconds: None,
+        expiry_destination: ExpiryDestination::Treasury,
+        push_settlement: PushSettlement::Off,
+        settlement_batch_size: 10,
+        strategy_batch_size: 10,
+        referral_share: Uint128::zero(),
+        fee_distribution: FeeDistribution {
+            staker_share: Uint128::zero(),
+            treasury_share: Uint128::new(FEE_PRECISION * 100),
+            burn_share: Uint128::zero(),
+        },
+        staking_rewards_addr: None,
+        fee_tiers: vec![],
+        volume_window_seconds: Uint128::zero(),
+        staking_addr: None,
+        fee_mode: FeeMode::OnStake,
+    };
+    CONFIG.save(deps.storage, &config)?;
+
+    /* Every round from the live one on is settled and booked the current way */
+    let untracked_rounds = match (
+        LIVE_ROUND.may_load(deps.storage)?,
+        NEXT_ROUND.may_load(deps.storage)?,
+    ) {
+        (Some(live_round), _) => live_round.id.u128(),
+        (None, Some(next_round)) => next_round.id.u128(),
+        (None, None) => NEXT_ROUND_ID.load(deps.storage)?,
+    };
+    UNTRACKED_ROUNDS.save(deps.storage, &untracked_rounds)?;
+    ROLLOVER_POOL.save(deps.storage, &Uint128::zero())?;
+    DUST.save(deps.storage, &Uint128::zero())?;
+
+    Ok(())
+}
+
+#[cfg_attr(
+    not(any(feature = "library", feature = "reference-oracle")),
+    entry_point
+)]
+pub fn execute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: ExecuteMsg,
+) -> Result<Response, ContractError> {
+    match msg {
+        ExecuteMsg::UpdateConfig { config } => execute_update_config(deps, info, env, *config),
+        ExecuteMsg::BetBear {
+            round_id,
+            amount,
+            player,
+        } => execute_bet(deps, info, env, round_id, Direction::Bear, amount, player),
+        ExecuteMsg::BetBull {
+            round_id,
+            amount,
+            player,
+        } => execute_bet(deps, info, env, round_id, Direction::Bull, amount, player),
+        ExecuteMsg::BetFromWinnings {
+            round_id,
+            direction,
+            amount,
+        } => execute_bet_from_winnings(deps, info, env, round_id, direction, amount),
+        ExecuteMsg::BetFromBalance {
+            round_id,
+            direction,
+            amount,
+            player,
+        } => execute_bet_from_balance(deps, info, env, round_id, direction, amount, player),
+        ExecuteMsg::ApproveOperator {
+            operator,
+            spend_limit,
+            expires,
+        } => execute_approve_operator(deps, info, operator, spend_limit, expires),
+        ExecuteMsg::RevokeOperator { operator } => execute_revoke_operator(deps, info, operator),
+        ExecuteMsg::RegisterReferrer { referrer } => {
+            execute_register_referrer(deps, info, referrer)
+        }
+        ExecuteMsg::ClaimReferralEarnings {} => execute_claim_referral_earnings(deps, info),
+        ExecuteMsg::DistributeFees {} => execute_distribute_fees(deps),
+        ExecuteMsg::DistributeWinnings { limit } => execute_distribute_winnings(deps, limit),
+        ExecuteMsg::RunStrategies { limit } => execute_run_strategies(deps, env, limit),
+        ExecuteMsg::Deposit { amount } => execute_deposit(deps, info, env, amount),
+        ExecuteMsg::Withdraw { amount } => execute_withdraw(deps, info, amount),
+        ExecuteMsg::CancelBet { round_id } => execute_cancel_bet(deps, info, env, round_id),
+        ExecuteMsg::SwitchDirection { round_id } => {
+            execute_switch_direction(deps, info, env, round_id)
+        }
+        ExecuteMsg::CloseRound {} => execute_close_round(deps, info, env),
+        ExecuteMsg::CollectWinnings { player, recipient } => {
+            execute_collect_winnings(deps, info, player, recipient)
+        }
+        ExecuteMsg::SweepDust {} => execute_sweep_dust(deps, info),
+        ExecuteMsg::ExpireRound { round_id, limit } => {
+            execute_expire_round(deps, env, round_id, limit)
+        }
+        ExecuteMsg::CreateStrategy {
+            kind,
+            amount,
+            rounds,
+            budget,
+        } => execute_create_strategy(deps, info, env, kind, amount, rounds, budget),
+        ExecuteMsg::ModifyStrategy {
+            kind,
+            amount,
+            rounds,
+            top_up,
+        } => execute_modify_strategy(deps, info, env, kind, amount, rounds, top_up),
+        ExecuteMsg::CancelStrategy {} => execute_cancel_strategy(deps, info),
+        ExecuteMsg::Hault {} => execute_update_hault(deps, info, env, true),
+        ExecuteMsg::Resume {} => execute_update_hault(deps, info, env, false),
+    }
+}
+
+fn execute_collect_winnings(
+    deps: DepsMut,
+    info: MessageInfo,
+    player: Option<String>,
+    recipient: Option<String>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let resp = Response::new();
+
+    let player = match player {
+        Some(player) => deps.api.addr_validate(&player)?,
+        None => info.sender.clone(),
+    };
+    /* Anyone may settle for a player, only the player may send the payout elsewhere */
+    let recipient = match recipient {
+        Some(recipient) if info.sender == player => deps.api.addr_validate(&recipient)?,
+        Some(_) => return Err(ContractError::Unauthorized {}),
+        None => player.clone(),
+    };
+
+    let winnings = take_winnings(deps, &player)?;
+
+    if winnings == Uint128::zero() {
+        return Err(ContractError::Std(StdError::generic_err(
+            "Nothing to claim",
+        )));
+    }
+
+    let msg_send_winnings = get_cw20_transfer_msg(&config.token_addr, &recipient, winnings)?;
+
+    Ok(resp
+        .add_message(msg_send_winnings)
+        .add_attribute("action", "collect-winnings")
+        .add_attribute("player", player)
+        .add_attribute("recipient", recipient)
+        .add_attribute("amount", winnings))
+}
+
+/**
+ * Settles every finished bet of the player and returns the total payout
+ */
+fn take_winnings(deps: DepsMut, player: &Addr) -> StdResult<Uint128> {
+    let mut winnings = Uint128::zero();
+
+    for claimable_round in settle_player(deps.as_ref(), player)? {
+        /* Only claimable once */
+        let round_id = claimable_round.round_id.u128();
+        remove_bet(deps.storage, round_id, player)?;
+        let mut round = ROUNDS.load(deps.storage, round_id)?;
+        record_payout(
+            deps.storage,
+            &mut round,
+            &claimable_round.direction,
+            claimable_round.amount,
+            claimable_round.payout,
+        )?;
+        ROUNDS.save(deps.storage, round_id, &round)?;
+
+        /* Count it up */
+        winnings += claimable_round.payout;
+    }
+
+    Ok(winnings)
+}
+
+fn execute_distribute_fees(deps: DepsMut) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    /* Fees of rounds that are not decided yet may still be refunded */
+    let mut reserved = Uint128::zero();
+    if let Some(next_round) = NEXT_ROUND.may_load(deps.storage)? {
+        reserved += next_round.fee_amount;
+    }
+    if let Some(live_round) = LIVE_ROUND.may_load(deps.storage)? {
+        reserved += live_round.fee_amount;
+    }
+
+    let fee = Uint128::from(ACCUMULATED_FEE.load(deps.storage)?) - reserved;
+    if fee.is_zero() {
+        return Err(ContractError::Std(StdError::generic_err(
+            "Nothing to distribute",
+        )));
+    }
+    ACCUMULATED_FEE.save(deps.storage, &reserved.u128())?;
+
+    let distribution = &config.fee_distribution;
+    let precision = Uint128::new(FEE_PRECISION * 100);
+    let burn_amount = fee.multiply_ratio(distribution.burn_share, precision);
+    let staker_amount = match config.staking_rewards_addr {
+        Some(_) => fee.multiply_ratio(distribution.staker_share, precision),
+        None => Uint128::zero(),
+    };
+    /* The treasury also takes the rounding leftovers */
+    let treasury_amount = fee - burn_amount - staker_amount;
+
+    let mut resp = Response::new();
+    if let Some(staking_rewards_addr) = &config.staking_rewards_addr {
+        if !staker_amount.is_zero() {
+            resp = resp.add_message(get_cw20_send_msg(
+                &config.token_addr,
+                staking_rewards_addr,
+                staker_amount,
+                to_binary(&StakingRewardsReceiveMsg::Fund {})?,
+            )?);
+        }
+    }
+    if !treasury_amount.is_zero() {
+        resp = resp.add_message(get_cw20_transfer_msg(
+            &config.token_addr,
+            &config.treasury_addr,
+            treasury_amount,
+        )?);
+    }
+    if !burn_amount.is_zero() {
+        resp = resp.add_message(get_cw20_burn_msg(&config.token_addr, burn_amount)?);
+    }
+
+    Ok(
+        resp.add_event(Event::new("prediction_bet").add_attributes(vec![
+            ("action", "betfi-distribute-fees".to_string()),
+            ("staker_amount", staker_amount.to_string()),
+            ("treasury_amount", treasury_amount.to_string()),
+            ("burn_amount", burn_amount.to_string()),
+        ])),
+    )
+}
+
+fn assert_valid_fee_distribution(distribution: &FeeDistribution) -> StdResult<()> {
+    let total = distribution.staker_share + distribution.treasury_share + distribution.burn_share;
+    if total != Uint128::new(FEE_PRECISION * 100) {
+        return Err(StdError::generic_err(format!(
+            "The fee distribution shares add up to {} instead of {}",
+            total,
+            FEE_PRECISION * 100
+        )));
+    }
+    Ok(())
+}
+
+/* A penalty over 100% would take more than the stake */
+fn assert_valid_cancel_penalty(config: &Config) -> StdResult<()> {
+    if config.cancel_penalty > Uint128::new(FEE_PRECISION * 100) {
+        return Err(StdError::generic_err(format!(
+            "The cancel penalty {} is over {}",
+            config.cancel_penalty,
+            FEE_PRECISION * 100
+        )));
+    }
+    Ok(())
+}
+
+fn assert_valid_oracles(deps: Deps, config: &Config) -> StdResult<()> {
+    for oracle in config.oracles.iter().chain(config.fallback_oracles.iter()) {
+        deps.api.addr_validate(oracle.addr().as_ref())?;
+    }
+    if config.oracle_quorum == 0 || config.oracle_quorum as usize > config.oracles.len() {
+        return Err(StdError::generic_err(format!(
+            "The oracle quorum must be between 1 and the {} oracles",
+            config.oracles.len()
+        )));
+    }
+    Ok(())
+}
+
+fn execute_sweep_dust(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != config.treasury_addr {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let dust = DUST.load(deps.storage)?;
+    if dust.is_zero() {
+        return Err(ContractError::Std(StdError::generic_err(
+            "Nothing to sweep",
+        )));
+    }
+    DUST.save(deps.storage, &Uint128::zero())?;
+
+    Ok(Response::new()
+        .add_message(get_cw20_transfer_msg(
+            &config.token_addr,
+            &config.treasury_addr,
+            dust,
+        )?)
+        .add_attribute("action", "sweep-dust")
+        .add_attribute("amount", dust))
+}
+
+fn execute_expire_round(
+    deps: DepsMut,
+    env: Env,
+    round_id: Uint128,
+    limit: u32,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let mut round = ROUNDS.load(deps.storage, round_id.u128())?;
+
+    if round.expired {
+        return Err(ContractError::RoundExpired {});
+    }
+    match round_expires_at(&config, &round) {
+        Some(expires_at) if env.block.time >= expires_at => {}
+        _ => return Err(ContractError::ClaimWindowOpen {}),
+    }
+
+    /* Forfeited bets are removed, so every batch resumes at the first bet left.
+     * One more than the batch tells whether the round is done */
+    let mut bets = bet_info_storage()
+        .prefix(round_id.u128())
+        .range(deps.storage, None, None, Order::Ascending)
+        .take(limit as usize + 1)
+        .map(|item| item.map(|(_, bet)| bet))
+        .collect::<StdResult<Vec<_>>>()?;
+    let is_round_done = bets.len() <= limit as usize;
+    bets.truncate(limit as usize);
+
+    let mut resp = Response::new();
+    let mut forfeited = Uint128::zero();
+    for bet in bets {
+        let payout = compute_bet_payout(&round, &bet);
+        remove_bet(deps.storage, round_id.u128(), &bet.player)?;
+        record_payout(deps.storage, &mut round, &bet.direction, bet.amount, payout)?;
+
+        if !payout.is_zero() {
+            forfeited += payout;
+            resp = resp.add_event(Event::new("prediction_bet").add_attributes(vec![
+                ("action", "betfi-winnings-expired".to_string()),
+                ("round", round_id.to_string()),
+                ("amount", payout.to_string()),
+                ("account", bet.player.to_string()),
+            ]));
+        }
+    }
+
+    round.expired = is_round_done;
+    ROUNDS.save(deps.storage, round_id.u128(), &round)?;
+
+    if !forfeited.is_zero() {
+        match config.expiry_destination {
+            ExpiryDestination::Treasury => {
+                resp = resp.add_message(get_cw20_transfer_msg(
+                    &config.token_addr,
+                    &config.treasury_addr,
+                    forfeited,
+                )?);
+            }
+            ExpiryDestination::Jackpot => {
+                ROLLOVER_POOL.update(deps.storage, |rollover| -> StdResult<_> {
+                    Ok(rollover + forfeited)
+                })?;
+            }
+        }
+    }
+
+    Ok(resp
+        .add_attribute("action", "expire-round")
+        .add_attribute("round", round_id)
+        .add_attribute("amount", forfeited)
+        .add_attribute("expired", is_round_done.to_string()))
+}
+
+fn execute_bet(
+    mut deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    round_id: Uint128,
+    dir: Direction,
+    gross: Uint128,
+    player: Option<String>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let player = resolve_player(deps.branch(), &env, &info.sender, player, gross)?;
+    let resp = place_bet(deps, &env, &player, round_id, dir, gross, false)?;
+
+    /* An operator only spends the player's allowance, never its own tokens */
+    let transfer_from_msg = get_cw20_transfer_from_msg(
+        &config.token_addr,
+        &player,
+        &env.contract.address,
+        //burn fee would be disappeared from user's wallet directly
+        gross,
+    )?;
+
+    Ok(resp.add_message(transfer_from_msg))
+}
+
+fn execute_bet_from_winnings(
+    mut deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    round_id: Uint128,
+    dir: Direction,
+    gross: Uint128,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    let winnings = take_winnings(deps.branch(), &info.sender)?;
+    if winnings < gross {
+        return Err(ContractError::InsufficientFunds {});
+    }
+
+    /* The stake never leaves the contract, only what is left over is paid out */
+    let mut resp = place_bet(deps, &env, &info.sender, round_id, dir, gross, false)?;
+    let remainder = winnings - gross;
+    if !remainder.is_zero() {
+        resp = resp.add_message(get_cw20_transfer_msg(
+            &config.token_addr,
+            &info.sender,
+            remainder,
+        )?);
+    }
+
+    Ok(resp
+        .add_attribute("action", "bet-from-winnings")
+        .add_attribute("winnings", winnings)
+        .add_attribute("paid_out", remainder))
+}
+
+fn execute_bet_from_balance(
+    mut deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    round_id: Uint128,
+    dir: Direction,
+    gross: Uint128,
+    player: Option<String>,
+) -> Result<Response, ContractError> {
+    let player = resolve_player(deps.branch(), &env, &info.sender, player, gross)?;
+    debit_balance(deps.storage, &player, gross)?;
+    place_bet(deps, &env, &player, round_id, dir, gross, true)
+}
+
+fn execute_approve_operator(
+    deps: DepsMut,
+    info: MessageInfo,
+    operator: String,
+    spend_limit: Uint128,
+    expires: Option<Timestamp>,
+) -> Result<Response, ContractError> {
+    let operator = deps.api.addr_validate(&operator)?;
+    OPERATORS.save(
+        deps.storage,
+        (&info.sender, &operator),
+        &OperatorApproval {
+            spend_limit,
+            expires,
+        },
+    )?;
+
+    Ok(
+        Response::new().add_event(Event::new("prediction_bet").add_attributes(vec![
+            ("action", "betfi-approve-operator".to_string()),
+            ("operator", operator.to_string()),
+            ("spend_limit", spend_limit.to_string()),
+            ("account", info.sender.to_string()),
+        ])),
+    )
+}
+
+fn execute_revoke_operator(
+    deps: DepsMut,
+    info: MessageInfo,
+    operator: String,
+) -> Result<Response, ContractError> {
+    let operator = deps.api.addr_validate(&operator)?;
+    OPERATORS.remove(deps.storage, (&info.sender, &operator));
+
+    Ok(
+        Response::new().add_event(Event::new("prediction_bet").add_attributes(vec![
+            ("action", "betfi-revoke-operator".to_string()),
+            ("operator", operator.to_string()),
+            ("account", info.sender.to_string()),
+        ])),
+    )
+}
+
+fn execute_register_referrer(
+    deps: DepsMut,
+    info: MessageInfo,
+    referrer: String,
+) -> Result<Response, ContractError> {
+    let referrer = deps.api.addr_validate(&referrer)?;
+    if referrer == info.sender {
+        return Err(ContractError::Std(StdError::generic_err(
+            "You can not refer yourself",
+        )));
+    }
+    if REFERRERS.has(deps.storage, &info.sender) {
+        return Err(ContractError::ReferrerAlreadySet {});
+    }
+
+    REFERRERS.save(deps.storage, &info.sender, &referrer)?;
+    REFERRAL_COUNTS.update(deps.storage, &referrer, |count| -> StdResult<_> {
+        Ok(count.unwrap_or_default() + 1)
+    })?;
+
+    Ok(
+        Response::new().add_event(Event::new("prediction_bet").add_attributes(vec![
+            ("action", "betfi-register-referrer".to_string()),
+            ("referrer", referrer.to_string()),
+            ("account", info.sender.to_string()),
+        ])),
+    )
+}
+
+fn execute_claim_referral_earnings(
+    deps: DepsMut,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let (earned, _, resolved) = resolve_referrals(deps.storage, &info.sender)?;
+    for round_id in resolved {
+        PENDING_REFERRALS.remove(deps.storage, (&info.sender, round_id));
+    }
+    let earnings = REFERRAL_EARNINGS
+        .may_load(deps.storage, &info.sender)?
+        .unwrap_or_default()
+        + earned;
+    if earnings.is_zero() {
+        return Err(ContractError::Std(StdError::generic_err(
+            "Nothing to claim",
+        )));
+    }
+    REFERRAL_EARNINGS.remove(deps.storage, &info.sender);
+
+    Ok(Response::new()
+        .add_message(get_cw20_transfer_msg(
+            &config.token_addr,
+            &info.sender,
+            earnings,
+        )?)
+        .add_event(Event::new("prediction_bet").add_attributes(vec![
+            ("action", "betfi-claim-referral".to_string()),
+            ("amount", earnings.to_string()),
+            ("account", info.sender.to_string()),
+        ])))
+}
+
+/**
+ * The player a bet is placed for. An operator betting for someone else
+ * needs an unexpired approval, which is charged the gross amount.
+ */
+fn resolve_player(
+    deps: DepsMut,
+    env: &Env,
+    sender: &Addr,
+    player: Option<String>,
+    gross: Uint128,
+) -> Result<Addr, ContractError> {
+    let player = match player {
+        Some(player) => deps.api.addr_validate(&player)?,
+        None => return Ok(sender.clone()),
+    };
+    if &player == sender {
+        return Ok(player);
+    }
+
+    let mut approval = OPERATORS
+        .may_load(deps.storage, (&player, sender))?
+        .ok_or(ContractError::Unauthorized {})?;
+    if let Some(expires) = approval.expires {
+        if env.block.time >= expires {
+            return Err(ContractError::OperatorExpired {});
+        }
+    }
+    approval.spend_limit = approval.spend_limit.checked_sub(gross).map_err(|_| {
+        ContractError::ExceedOperatorSpendLimit {
+            remaining: approval.spend_limit,
+        }
+    })?;
+    OPERATORS.save(deps.storage, (&player, sender), &approval)?;
+
+    Ok(player)
+}
+
+fn execute_deposit(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let balance = credit_balance(deps.storage, &info.sender, amount)?;
+
+    Ok(Response::new()
+        .add_message(get_cw20_transfer_from_msg(
+            &config.token_addr,
+            &info.sender,
+            &env.contract.address,
+            amount,
+        )?)
+        .add_event(Event::new("prediction_bet").add_attributes(vec![
+            ("action", "betfi-deposit".to_string()),
+            ("amount", amount.to_string()),
+            ("balance", balance.to_string()),
+            ("account", info.sender.to_string()),
+        ])))
+}
+
+fn execute_withdraw(
+    deps: DepsMut,
+    info: MessageInfo,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let balance = debit_balance(deps.storage, &info.sender, amount)?;
+
+    Ok(Response::new()
+        .add_message(get_cw20_transfer_msg(
+            &config.token_addr,
+            &info.sender,
+            amount,
+        )?)
+        .add_event(Event::new("prediction_bet").add_attributes(vec![
+            ("action", "betfi-withdraw".to_string()),
+            ("amount", amount.to_string()),
+            ("balance", balance.to_string()),
+            ("account", info.sender.to_string()),
+        ])))
+}
+
+fn credit_balance(storage: &mut dyn Storage, player: &Addr, amount: Uint128) -> StdResult<Uint128> {
+    BALANCES.update(storage, player, |balance| -> StdResult<_> {
+        Ok(balance.unwrap_or_default() + amount)
+    })
+}
+
+fn debit_balance(
+    storage: &mut dyn Storage,
+    player: &Addr,
+    amount: Uint128,
+) -> Result<Uint128, ContractError> {
+    let balance = BALANCES
+        .may_load(storage, player)?
+        .unwrap_or_default()
+        .checked_sub(amount)
+        .map_err(|_| ContractError::InsufficientFunds {})?;
+    BALANCES.save(storage, player, &balance)?;
+    Ok(balance)
+}
+
+fn execute_distribute_winnings(deps: DepsMut, limit: u32) -> Result<Response, ContractError> {
+    let resp = distribute_winnings(deps, Response::new(), limit)?;
+    Ok(resp.add_attribute("action", "distribute-winnings"))
+}
+
+/**
+ * Pushes payouts of finished rounds to their players, at most `limit` bets per call.
+ * Rounds are worked through oldest first, each resuming after the last player handled.
+ * Balance-funded bets are always credited, the rest follow `push_settlement`.
+ */
+fn distribute_winnings(deps: DepsMut, mut resp: Response, limit: u32) -> StdResult<Response> {
+    let config = CONFIG.load(deps.storage)?;
+    let mut remaining = limit as usize;
+    let mut last_round_id = None;
+
+    while remaining > 0 {
+        let (round_id, cursor) = match PENDING_SETTLEMENTS
+            .range(
+                deps.storage,
+                last_round_id.map(Bound::exclusive),
+                None,
+                Order::Ascending,
+            )
+            .next()
+            .transpose()?
+        {
+            Some(pending) => pending,
+            None => break,
+        };
+        last_round_id = Some(round_id);
+        let mut round = ROUNDS.load(deps.storage, round_id)?;
+
+        /* Without push settlement only the balance-funded bets are pushed */
+        let mut players = match config.push_settlement {
+            PushSettlement::Off => BALANCE_BETS
+                .prefix(round_id)
+                .keys(
+                    deps.storage,
+                    cursor.last_player.as_ref().map(Bound::exclusive),
+                    None,
+                    Order::Ascending,
+                )
+                .take(remaining + 1)
+                .collect::<StdResult<Vec<_>>>()?,
+            _ => bet_info_storage()
+                .prefix(round_id)
+                .keys(
+                    deps.storage,
+                    cursor.last_player.map(Bound::exclusive),
+                    None,
+                    Order::Ascending,
+                )
+                .take(remaining + 1)
+                .collect::<StdResult<Vec<_>>>()?,
+        };
+        /* One more than the batch tells whether the round is done */
+        let is_round_done = players.len() <= remaining;
+        players.truncate(remaining);
+        remaining -= players.len();
+
+        for player in &players {
+            let bet = bet_info_storage().load(deps.storage, bet_info_key(round_id, player))?;
+            let payout = compute_bet_payout(&round, &bet);
+            remove_bet(deps.storage, round_id, player)?;
+            record_payout(deps.storage, &mut round, &bet.direction, bet.amount, payout)?;
+            if payout.is_zero() {
+                continue;
+            }
+
+            let action = match (bet.from_balance, &config.push_settlement) {
+                (false, PushSettlement::Transfer) => {
+                    resp = resp.add_message(get_cw20_transfer_msg(
+                        &config.token_addr,
+                        player,
+                        payout,
+                    )?);
+                    "betfi-send-winnings"
+                }
+                _ => {
+                    credit_balance(deps.storage, player, payout)?;
+                    "betfi-credit-winnings"
+                }
+            };
+            resp = resp.add_event(Event::new("prediction_bet").add_attributes(vec![
+                ("action", action.to_string()),
+                ("round", round_id.to_string()),
+                ("amount", payout.to_string()),
+                ("account", player.to_string()),
+            ]));
+        }
+
+        ROUNDS.save(deps.storage, round_id, &round)?;
+        if is_round_done {
+            PENDING_SETTLEMENTS.remove(deps.storage, round_id);
+        } else {
+            PENDING_SETTLEMENTS.save(
+                deps.storage,
+                round_id,
+                &SettlementCursor {
+                    last_player: players.last().cloned(),
+                },
+            )?;
+        }
+    }
+
+    Ok(resp)
+}
+
+/* Whether a finished round has bets for distribute_winnings to push */
+fn has_bets_to_push(storage: &dyn Storage, config: &Config, round_id: u128) -> bool {
+    if config.settlement_batch_size == 0 {
+        return false;
+    }
+    match config.push_settlement {
+        PushSettlement::Off => BALANCE_BETS
+            .prefix(round_id)
+            .keys(storage, None, None, Order::Ascending)
+            .next()
+            .is_some(),
+        _ => bet_info_storage()
+            .prefix(round_id)
+            .keys(storage, None, None, Order::Ascending)
+            .next()
+            .is_some(),
+    }
+}
+
+fn execute_create_strategy(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    kind: StrategyKind,
+    amount: Uint128,
+    rounds: u32,
+    budget: Uint128,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if STRATEGIES.has(deps.storage, &info.sender) {
+        return Err(ContractError::Std(StdError::generic_err(
+            "You already have a strategy, modify or cancel it",
+        )));
+    }
+
+    let strategy = Strategy {
+        kind,
+        amount,
+        rounds_remaining: rounds,
+        budget,
+    };
+    assert_valid_strategy(&config, &strategy)?;
+    STRATEGIES.save(deps.storage, &info.sender, &strategy)?;
+
+    let mut resp = Response::new();
+    if !budget.is_zero() {
+        resp = resp.add_message(get_cw20_transfer_from_msg(
+            &config.token_addr,
+            &info.sender,
+            &env.contract.address,
+            budget,
+        )?);
+    }
+    Ok(
+        resp.add_event(Event::new("prediction_bet").add_attributes(vec![
+            ("action", "betfi-create-strategy".to_string()),
+            ("amount", amount.to_string()),
+            ("rounds", rounds.to_string()),
+            ("budget", budget.to_string()),
+            ("account", info.sender.to_string()),
+        ])),
+    )
+}
+
+fn execute_modify_strategy(
     deps: DepsMut,
-    env: Env,
     info: MessageInfo,
-    msg: ExecuteMsg,
+    env: Env,
+    kind: Option<StrategyKind>,
+    amount: Option<Uint128>,
+    rounds: Option<u32>,
+    top_up: Option<Uint128>,
 ) -> Result<Response, ContractError> {
-    match msg {
-        ExecuteMsg::UpdateConfig { config } => execute_update_config(deps, info, env, config),
-        ExecuteMsg::BetBear { round_id, amount } => {
-            execute_bet(deps, info, env, round_id, Direction::Bear, amount)
-        }
-        ExecuteMsg::BetBull { round_id, amount } => {
-            execute_bet(deps, info, env, round_id, Direction::Bull, amount)
-        }
-        ExecuteMsg::CloseRound {} => execute_close_round(deps, info, env),
-        ExecuteMsg::CollectWinnings {} => execute_collect_winnings(deps, info),
-        ExecuteMsg::Hault {} => execute_update_hault(deps, info, env, true),
-        ExecuteMsg::Resume {} => execute_update_hault(deps, info, env, false),
+    let config = CONFIG.load(deps.storage)?;
+    let mut strategy = STRATEGIES
+        .may_load(deps.storage, &info.sender)?
+        .ok_or(ContractError::StrategyNotFound {})?;
+
+    if let Some(kind) = kind {
+        strategy.kind = kind;
     }
+    if let Some(amount) = amount {
+        strategy.amount = amount;
+    }
+    if let Some(rounds) = rounds {
+        strategy.rounds_remaining = rounds;
+    }
+    let top_up = top_up.unwrap_or_default();
+    strategy.budget += top_up;
+    assert_valid_strategy(&config, &strategy)?;
+    STRATEGIES.save(deps.storage, &info.sender, &strategy)?;
+
+    let mut resp = Response::new();
+    if !top_up.is_zero() {
+        resp = resp.add_message(get_cw20_transfer_from_msg(
+            &config.token_addr,
+            &info.sender,
+            &env.contract.address,
+            top_up,
+        )?);
+    }
+    Ok(
+        resp.add_event(Event::new("prediction_bet").add_attributes(vec![
+            ("action", "betfi-modify-strategy".to_string()),
+            ("amount", strategy.amount.to_string()),
+            ("rounds", strategy.rounds_remaining.to_string()),
+            ("budget", strategy.budget.to_string()),
+            ("account", info.sender.to_string()),
+        ])),
+    )
 }
 
-fn execute_collect_winnings(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+fn execute_cancel_strategy(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
     let config = CONFIG.load(deps.storage)?;
-    let mut winnings = Uint128::zero();
-    let resp = Response::new();
+    let strategy = STRATEGIES
+        .may_load(deps.storage, &info.sender)?
+        .ok_or(ContractError::StrategyNotFound {})?;
+    STRATEGIES.remove(deps.storage, &info.sender);
 
-    // let no_duplicate_rounds: HashSet<u128> =
-    //     HashSet::from_iter(rounds.iter().cloned());
-
-    let my_game_list = query_my_games_without_limit(deps.as_ref(), info.sender.clone())?;
-
-    for game in my_game_list.my_game_list {
-        let next_round_id = NEXT_ROUND_ID.load(deps.storage)?;
-        if game.round_id < (Uint128::new(next_round_id) - Uint128::new(1)) {
-            let round_id = game.round_id;
-            let round = ROUNDS.load(deps.storage, round_id.u128())?;
-
-            let pool_shares = round.bear_amount + round.bull_amount;
-            let bet_info_key = bet_info_key(round_id.u128(), &info.sender);
-
-            bet_info_storage().remove(deps.storage, bet_info_key.clone())?;
-
-            if round.bear_amount == Uint128::zero() || round.bull_amount == Uint128::zero() {
-                winnings += game.amount;
-            } else {
-                let round_winnings = match round.winner {
-                    Some(Direction::Bull) => {
-                        /* Only claimable once */
-                        match game.direction {
-                            Direction::Bull => {
-                                let won_shares = game.amount;
-                                pool_shares.multiply_ratio(won_shares, round.bull_amount)
-                            }
-                            Direction::Bear => Uint128::zero(),
-                        }
-                    }
-                    Some(Direction::Bear) => {
-                        /* Only claimable once */
-                        match game.direction {
-                            Direction::Bull => Uint128::zero(),
-                            Direction::Bear => {
-                                let won_shares = game.amount;
-                                pool_shares.multiply_ratio(won_shares, round.bear_amount)
-                            }
-                        }
-                    }
-                    None => {
-                        /* Only claimable once */
-                        game.amount
-                    }
-                };
-
-                /* Count it up */
-                winnings += round_winnings;
-            }
-        }
+    let mut resp = Response::new();
+    if !strategy.budget.is_zero() {
+        resp = resp.add_message(get_cw20_transfer_msg(
+            &config.token_addr,
+            &info.sender,
+            strategy.budget,
+        )?);
     }
+    Ok(
+        resp.add_event(Event::new("prediction_bet").add_attributes(vec![
+            ("action", "betfi-cancel-strategy".to_string()),
+            ("amount", strategy.budget.to_string()),
+            ("account", info.sender.to_string()),
+        ])),
+    )
+}
 
-    if winnings == Uint128::zero() {
+fn assert_valid_strategy(config: &Config, strategy: &Strategy) -> Result<(), ContractError> {
+    if strategy.amount < config.minimum_bet {
+        return Err(ContractError::InsufficientFundsForBet {});
+    }
+    if let Some(maximum) = config.maximum_bet {
+        if strategy.amount > maximum {
+            return Err(ContractError::ExceedMaximumBet { maximum });
+        }
+    }
+    if strategy.rounds_remaining == 0 {
         return Err(ContractError::Std(StdError::generic_err(
-            "Nothing to claim",
+            "A strategy has to run for at least one round",
+        )));
+    }
+    if strategy.budget < strategy.amount {
+        return Err(ContractError::Std(StdError::generic_err(
+            "A strategy's budget has to cover at least one bet",
         )));
     }
+    Ok(())
+}
 
-    let msg_send_winnings: CosmosMsg;
+/* Strategies bet on every round that opens for betting, a keeper continues with RunStrategies */
+fn start_strategies(
+    deps: DepsMut,
+    env: &Env,
+    resp: Response,
+    round_id: Uint128,
+    config: &Config,
+) -> StdResult<Response> {
+    STRATEGY_CURSOR.save(
+        deps.storage,
+        &StrategyCursor {
+            round_id,
+            last_player: None,
+        },
+    )?;
+    run_strategies(deps, env, resp, config.strategy_batch_size)
+}
 
-    msg_send_winnings = get_cw20_transfer_msg(&config.token_addr, &info.sender, winnings)?;
+fn execute_run_strategies(deps: DepsMut, env: Env, limit: u32) -> Result<Response, ContractError> {
+    if let Some(cursor) = STRATEGY_CURSOR.may_load(deps.storage)? {
+        assert_strategy_round_open(deps.as_ref(), &env, &cursor)?;
+    }
+    let resp = run_strategies(deps, &env, Response::new(), limit)?;
+    Ok(resp.add_attribute("action", "run-strategies"))
+}
 
-    Ok(resp
-        .add_message(msg_send_winnings)
-        .add_attribute("action", "collect-winnings")
-        .add_attribute("amount", winnings))
+/* The round the strategies are running on still takes bets */
+fn assert_strategy_round_open(deps: Deps, env: &Env, cursor: &StrategyCursor) -> StdResult<()> {
+    assert_not_haulted(deps)?;
+    let round = assert_is_current_round(deps, cursor.round_id)?;
+    assert_bets_open(&CONFIG.load(deps.storage)?, env, &round)
 }
 
-fn execute_bet(
+/**
+ * Places the bets of strategies on the round open for betting, at most `limit` per call.
+ * While the round takes bets, every strategy run uses up one of its rounds, whether its bet
+ * could be placed or not, and only placed bets are charged to the budget.
+ * Bets are balance-funded, so their winnings land in the player's balance.
+ * Finished strategies, and those whose budget no longer covers a bet, return what is
+ * left of their budget to the player's balance.
+ */
+fn run_strategies(
+    mut deps: DepsMut,
+    env: &Env,
+    mut resp: Response,
+    limit: u32,
+) -> StdResult<Response> {
+    let cursor = match STRATEGY_CURSOR.may_load(deps.storage)? {
+        Some(cursor) => cursor,
+        None => return Ok(resp),
+    };
+    /* A round that can't take bets is no strategy's fault, none of them is charged a round */
+    if assert_strategy_round_open(deps.as_ref(), env, &cursor).is_err() {
+        return Ok(resp);
+    }
+    let last_winner = ROUNDS
+        .range(deps.storage, None, None, Order::Descending)
+        .next()
+        .transpose()?
+        .and_then(|(_, round)| round.winner);
+
+    let strategies = STRATEGIES
+        .range(
+            deps.storage,
+            cursor.last_player.as_ref().map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(limit as usize)
+        .collect::<StdResult<Vec<_>>>()?;
+    if strategies.len() < limit as usize {
+        STRATEGY_CURSOR.remove(deps.storage);
+    } else {
+        STRATEGY_CURSOR.save(
+            deps.storage,
+            &StrategyCursor {
+                round_id: cursor.round_id,
+                last_player: strategies.last().map(|(player, _)| player.clone()),
+            },
+        )?;
+    }
+
+    for (player, mut strategy) in strategies {
+        let direction = match &strategy.kind {
+            StrategyKind::Fixed { direction } => Some(direction.clone()),
+            StrategyKind::FollowWinner {} => last_winner.clone(),
+        };
+        if let Some(direction) = direction {
+            if strategy.budget >= strategy.amount {
+                if let Ok(bet_resp) = place_bet(
+                    deps.branch(),
+                    env,
+                    &player,
+                    cursor.round_id,
+                    direction,
+                    strategy.amount,
+                    true,
+                ) {
+                    strategy.budget -= strategy.amount;
+                    resp = resp.add_events(bet_resp.events);
+                }
+            }
+        }
+        strategy.rounds_remaining -= 1;
+
+        if strategy.rounds_remaining > 0 && strategy.budget >= strategy.amount {
+            STRATEGIES.save(deps.storage, &player, &strategy)?;
+            continue;
+        }
+        STRATEGIES.remove(deps.storage, &player);
+        if !strategy.budget.is_zero() {
+            credit_balance(deps.storage, &player, strategy.budget)?;
+        }
+        resp = resp.add_event(Event::new("prediction_bet").add_attributes(vec![
+            ("action", "betfi-strategy-done".to_string()),
+            ("amount", strategy.budget.to_string()),
+            ("account", player.to_string()),
+        ]));
+    }
+
+    Ok(resp)
+}
+
+/**
+ * Books a bet for `player` without moving any tokens, the caller funds it
+ */
+fn place_bet(
     deps: DepsMut,
-    info: MessageInfo,
-    env: Env,
+    env: &Env,
+    player: &Addr,
     round_id: Uint128,
     dir: Direction,
     gross: Uint128,
+    from_balance: bool,
 ) -> Result<Response, ContractError> {
     assert_not_haulted(deps.as_ref())?;
 
@@ -162,112 +1181,294 @@ fn execute_bet(
     let mut resp = Response::new();
     let config = CONFIG.load(deps.storage)?;
 
-    let current_time = env.block.time.seconds();
-    let open_time = bet_round.open_time.seconds();
-
     if gross < config.minimum_bet {
         return Err(ContractError::InsufficientFundsForBet {});
     }
 
-    if env.block.time > bet_round.open_time {
-        return Err(ContractError::Std(StdError::generic_err(format!(
-            "Round {} stopped accepting bids {} second(s) ago; the next round has not yet begun",
-            round_id,
-            (current_time - open_time)
-        ))));
+    if let Some(maximum) = config.maximum_bet {
+        if gross > maximum {
+            return Err(ContractError::ExceedMaximumBet { maximum });
+        }
     }
 
-    let staker_fee = compute_gaming_fee(deps.as_ref(), gross)?;
-    ACCUMULATED_FEE.update(deps.storage, |fee_before| -> Result<u128, StdError> {
-        Ok(fee_before + staker_fee.u128())
-    })?;
+    assert_bets_open(&config, env, &bet_round)?;
 
+    let staker_fee = match config.fee_mode {
+        FeeMode::OnStake => compute_gaming_fee(deps.as_ref(), env, player, gross)?,
+        /* Raked from the pool at close instead */
+        FeeMode::OnWinnings => Uint128::zero(),
+    };
     /* Deduct open + burn fee from the gross amount */
     let bet_amt = gross - staker_fee;
 
-    TOTAL_VOLUME.update(deps.storage, |mut volume| -> StdResult<_> {
-        volume = volume + bet_amt;
-        Ok(volume)
-    })?;
-
-    let bet_info_key = bet_info_key(round_id.u128(), &info.sender.clone());
+    let bet_info_key = bet_info_key(round_id.u128(), player);
 
     let bet_info = bet_info_storage().may_load(deps.storage, bet_info_key.clone())?;
 
-    if !bet_info.is_none() {
-        return Err(ContractError::Std(StdError::generic_err(format!(
-            "You are already bet for this game for {}, with amount: {}",
-            bet_info.clone().unwrap().direction.to_string(),
-            bet_info.unwrap().amount
-        ))));
+    /* Topping up is allowed, hedging the other side is not */
+    let previous_bet = match bet_info {
+        Some(bet_info) if bet_info.direction != dir => {
+            return Err(ContractError::Std(StdError::generic_err(format!(
+                "You are already bet for this game for {}, with amount: {}",
+                bet_info.direction, bet_info.amount
+            ))));
+        }
+        bet_info => bet_info,
+    };
+    let player_round_amt = previous_bet
+        .as_ref()
+        .map_or(bet_amt, |bet| bet.amount + bet_amt);
+
+    if let Some(maximum) = config.maximum_player_round_bet {
+        if player_round_amt > maximum {
+            return Err(ContractError::ExceedPlayerRoundMaximum { maximum });
+        }
     }
 
-    match dir {
+    let (side_total, other_side_total) = match dir {
         Direction::Bull => {
-            // BULL_BETS.save(deps.storage, bet_key, &bet_amt.u128())?;
-            bet_info_storage().save(
-                deps.storage,
-                bet_info_key.clone(),
-                &BetInfo {
-                    player: info.sender.clone(),
-                    round_id,
-                    amount: bet_amt,
-                    direction: Direction::Bull,
-                },
-            )?;
             bet_round.bull_amount += bet_amt;
-            NEXT_ROUND.save(deps.storage, &bet_round)?;
-            resp = resp.add_event(Event::new("prediction_bet").add_attributes(vec![
-                ("action", "betfi-bet".to_string()),
-                ("round", round_id.to_string()),
-                ("direction", "bull".to_string()),
-                ("amount", bet_amt.to_string()),
-                ("round_bull_total", bet_round.bull_amount.to_string()),
-                ("account", info.sender.to_string()),
-            ]));
+            (bet_round.bull_amount, bet_round.bear_amount)
         }
         Direction::Bear => {
-            bet_info_storage().save(
+            bet_round.bear_amount += bet_amt;
+            (bet_round.bear_amount, bet_round.bull_amount)
+        }
+    };
+
+    /* An empty opposite side can't be measured against, the first bet is always allowed */
+    if let Some(max_ratio) = config.max_pool_ratio {
+        if !other_side_total.is_zero() && side_total > other_side_total * max_ratio {
+            return Err(ContractError::ExceedPoolRatio { max_ratio });
+        }
+    }
+
+    /*
+     * Nothing is written before every check passed, strategies skip failed bets in place.
+     * A referrer's share of the fee is held with the round until it is decided, the rest
+     * accumulates.
+     */
+    let referral_fee = match REFERRERS.may_load(deps.storage, player)? {
+        Some(referrer) => {
+            let referral_fee = compute_referral_fee(&config, staker_fee)?;
+            PENDING_REFERRALS.update(
                 deps.storage,
-                bet_info_key.clone(),
-                &BetInfo {
-                    player: info.sender.clone(),
-                    round_id,
-                    amount: bet_amt,
-                    direction: Direction::Bear,
-                },
+                (&referrer, round_id.u128()),
+                |pending| -> StdResult<_> { Ok(pending.unwrap_or_default() + referral_fee) },
             )?;
-            bet_round.bear_amount += bet_amt;
-            NEXT_ROUND.save(deps.storage, &bet_round)?;
-            resp = resp.add_event(Event::new("prediction_bet").add_attributes(vec![
-                ("action", "betfi-bet".to_string()),
-                ("round", round_id.to_string()),
-                ("direction", "bear".to_string()),
-                ("amount", bet_amt.to_string()),
-                ("round_bear_total", bet_round.bear_amount.to_string()),
-                ("account", info.sender.to_string()),
-            ]));
+            referral_fee
         }
+        None => Uint128::zero(),
+    };
+    let house_fee = staker_fee - referral_fee;
+    ACCUMULATED_FEE.update(deps.storage, |fee_before| -> Result<u128, StdError> {
+        Ok(fee_before + house_fee.u128())
+    })?;
+    /* Kept with the round until it is decided, a refunded round gives it back */
+    bet_round.fee_amount += house_fee;
+    TOTAL_VOLUME.update(deps.storage, |mut volume| -> StdResult<_> {
+        volume += bet_amt;
+        Ok(volume)
+    })?;
+    record_volume(deps.storage, &config, player, env.block.time, gross)?;
+    bet_info_storage().save(
+        deps.storage,
+        bet_info_key,
+        &BetInfo {
+            player: player.clone(),
+            round_id,
+            amount: player_round_amt,
+            gross_amount: previous_bet
+                .as_ref()
+                .map_or(gross, |bet| bet.gross_amount + gross),
+            fee: previous_bet
+                .as_ref()
+                .map_or(house_fee, |bet| bet.fee + house_fee),
+            referral_fee: previous_bet
+                .as_ref()
+                .map_or(referral_fee, |bet| bet.referral_fee + referral_fee),
+            direction: dir.clone(),
+            from_balance: previous_bet
+                .as_ref()
+                .map_or(from_balance, |bet| bet.from_balance || from_balance),
+        },
+    )?;
+    if from_balance {
+        BALANCE_BETS.save(deps.storage, (round_id.u128(), player), &Empty {})?;
     }
+    NEXT_ROUND.save(deps.storage, &bet_round)?;
+    resp = resp.add_event(Event::new("prediction_bet").add_attributes(vec![
+        ("action", "betfi-bet".to_string()),
+        ("round", round_id.to_string()),
+        ("direction", dir.to_string()),
+        ("amount", bet_amt.to_string()),
+        (
+            match dir {
+                Direction::Bull => "round_bull_total",
+                Direction::Bear => "round_bear_total",
+            },
+            side_total.to_string(),
+        ),
+        ("account", player.to_string()),
+    ]));
 
-    let contract_addrss = env.contract.address;
+    Ok(resp)
+}
 
-    let transfer_from_msg = get_cw20_transfer_from_msg(
-        &config.token_addr,
+fn execute_cancel_bet(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    round_id: Uint128,
+) -> Result<Response, ContractError> {
+    assert_not_haulted(deps.as_ref())?;
+
+    let mut bet_round = assert_is_current_round(deps.as_ref(), round_id)?;
+    let config = CONFIG.load(deps.storage)?;
+    assert_cancel_window_open(&config, &env, &bet_round)?;
+
+    let bet_info_key = bet_info_key(round_id.u128(), &info.sender);
+    let bet_info = bet_info_storage()
+        .may_load(deps.storage, bet_info_key.clone())?
+        .ok_or(ContractError::BetNotFound {})?;
+
+    let penalty = compute_cancel_penalty(&config, bet_info.amount)?;
+    let refund = bet_info.amount - penalty;
+
+    ACCUMULATED_FEE.update(deps.storage, |fee_before| -> Result<u128, StdError> {
+        Ok(fee_before + penalty.u128())
+    })?;
+    TOTAL_VOLUME.update(deps.storage, |mut volume| -> StdResult<_> {
+        volume -= bet_info.amount;
+        Ok(volume)
+    })?;
+    remove_volume(
+        deps.storage,
+        &config,
         &info.sender,
-        &contract_addrss,
-        //burn fee would be disappeared from user's wallet directly
-        gross,
+        env.block.time,
+        bet_info.gross_amount,
     )?;
-    resp = resp.add_message(transfer_from_msg);
+
+    match bet_info.direction {
+        Direction::Bull => bet_round.bull_amount -= bet_info.amount,
+        Direction::Bear => bet_round.bear_amount -= bet_info.amount,
+    }
+    /* The fee of a cancelled bet is no longer refundable, the referrer has earned their share */
+    bet_round.fee_amount -= bet_info.fee;
+    if !bet_info.referral_fee.is_zero() {
+        let referrer = REFERRERS.load(deps.storage, &info.sender)?;
+        PENDING_REFERRALS.update(
+            deps.storage,
+            (&referrer, round_id.u128()),
+            |pending| -> StdResult<_> { Ok(pending.unwrap_or_default() - bet_info.referral_fee) },
+        )?;
+        REFERRAL_EARNINGS.update(deps.storage, &referrer, |earnings| -> StdResult<_> {
+            Ok(earnings.unwrap_or_default() + bet_info.referral_fee)
+        })?;
+    }
+    NEXT_ROUND.save(deps.storage, &bet_round)?;
+    remove_bet(deps.storage, round_id.u128(), &info.sender)?;
+
+    let mut resp = Response::new().add_event(Event::new("prediction_bet").add_attributes(vec![
+        ("action", "betfi-cancel-bet".to_string()),
+        ("round", round_id.to_string()),
+        ("direction", bet_info.direction.to_string()),
+        ("amount", bet_info.amount.to_string()),
+        ("penalty", penalty.to_string()),
+        ("account", info.sender.to_string()),
+    ]));
+
+    /* Balance-funded bets are refunded to the balance */
+    if bet_info.from_balance {
+        credit_balance(deps.storage, &info.sender, refund)?;
+    } else if !refund.is_zero() {
+        resp = resp.add_message(get_cw20_transfer_msg(
+            &config.token_addr,
+            &info.sender,
+            refund,
+        )?);
+    }
 
     Ok(resp)
 }
 
-fn execute_close_round(
+fn execute_switch_direction(
     deps: DepsMut,
     info: MessageInfo,
     env: Env,
+    round_id: Uint128,
+) -> Result<Response, ContractError> {
+    assert_not_haulted(deps.as_ref())?;
+
+    let mut bet_round = assert_is_current_round(deps.as_ref(), round_id)?;
+    let config = CONFIG.load(deps.storage)?;
+    assert_cancel_window_open(&config, &env, &bet_round)?;
+
+    let bet_info_key = bet_info_key(round_id.u128(), &info.sender);
+    let mut bet_info = bet_info_storage()
+        .may_load(deps.storage, bet_info_key.clone())?
+        .ok_or(ContractError::BetNotFound {})?;
+
+    /* Switching pays the same penalty as cancelling so sides can't be flipped for free */
+    let penalty = compute_cancel_penalty(&config, bet_info.amount)?;
+    let switched_amt = bet_info.amount - penalty;
+
+    let (side_total, other_side_total) = match bet_info.direction {
+        Direction::Bull => {
+            bet_round.bull_amount -= bet_info.amount;
+            bet_round.bear_amount += switched_amt;
+            bet_info.direction = Direction::Bear;
+            (bet_round.bear_amount, bet_round.bull_amount)
+        }
+        Direction::Bear => {
+            bet_round.bear_amount -= bet_info.amount;
+            bet_round.bull_amount += switched_amt;
+            bet_info.direction = Direction::Bull;
+            (bet_round.bull_amount, bet_round.bear_amount)
+        }
+    };
+
+    /* The side switched to is held to the same ratio as a new bet on it */
+    if let Some(max_ratio) = config.max_pool_ratio {
+        if !other_side_total.is_zero() && side_total > other_side_total * max_ratio {
+            return Err(ContractError::ExceedPoolRatio { max_ratio });
+        }
+    }
+
+    ACCUMULATED_FEE.update(deps.storage, |fee_before| -> Result<u128, StdError> {
+        Ok(fee_before + penalty.u128())
+    })?;
+    TOTAL_VOLUME.update(deps.storage, |mut volume| -> StdResult<_> {
+        volume -= penalty;
+        Ok(volume)
+    })?;
+    remove_volume(deps.storage, &config, &info.sender, env.block.time, penalty)?;
+    bet_info.amount = switched_amt;
+    /* What the player still has in, a later cancel takes back only that */
+    bet_info.gross_amount = bet_info.gross_amount.saturating_sub(penalty);
+
+    NEXT_ROUND.save(deps.storage, &bet_round)?;
+    bet_info_storage().save(deps.storage, bet_info_key, &bet_info)?;
+
+    Ok(
+        Response::new().add_event(Event::new("prediction_bet").add_attributes(vec![
+            ("action", "betfi-switch-direction".to_string()),
+            ("round", round_id.to_string()),
+            ("direction", bet_info.direction.to_string()),
+            ("amount", bet_info.amount.to_string()),
+            ("penalty", penalty.to_string()),
+            ("round_bull_total", bet_round.bull_amount.to_string()),
+            ("round_bear_total", bet_round.bear_amount.to_string()),
+            ("account", info.sender.to_string()),
+        ])),
+    )
+}
+
+fn execute_close_round(
+    mut deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
 ) -> Result<Response, ContractError> {
     assert_not_haulted(deps.as_ref())?;
     assert_is_admin(deps.as_ref(), info, env.clone())?;
@@ -275,33 +1476,105 @@ fn execute_close_round(
     let config = CONFIG.load(deps.storage)?;
     let mut resp: Response = Response::new();
 
+    /* Every close is a TWAP sample, taken before any price is read.
+     * A pool that can't be sampled only loses this sample, it must not stop the game */
+    for oracle in config.oracles.iter().chain(config.fallback_oracles.iter()) {
+        if let OracleSource::Wasmswap {
+            addr,
+            twap_window_seconds: Some(window),
+        } = oracle
+        {
+            if sample_twap(deps.branch(), addr, *window, now).is_err() {
+                resp = resp.add_attribute("twap_sample_failed", addr.to_string());
+            }
+        }
+    }
+
     /*
      * Close the live round if it is finished
      */
     let maybe_live_round = LIVE_ROUND.may_load(deps.storage)?;
-    match &maybe_live_round {
-        Some(live_round) => {
-            if now >= live_round.close_time {
-                let finished_round = compute_round_close(deps.as_ref(), live_round)?;
-                ROUNDS.save(deps.storage, live_round.id.u128(), &finished_round)?;
+    if let Some(live_round) = &maybe_live_round {
+        if now >= live_round.close_time {
+            let close_price = get_current_price(deps.as_ref(), now)?;
+            let finished_round =
+                compute_round_close(deps.as_ref(), &env, live_round, &close_price)?;
+            ROUNDS.save(deps.storage, live_round.id.u128(), &finished_round)?;
+            if has_bets_to_push(deps.storage, &config, live_round.id.u128()) {
+                PENDING_SETTLEMENTS.save(
+                    deps.storage,
+                    live_round.id.u128(),
+                    &SettlementCursor { last_player: None },
+                )?;
+            }
+            ACCUMULATED_FEE.update(deps.storage, |fee| -> StdResult<_> {
+                Ok(fee + finished_round.rake.u128())
+            })?;
+            /* Refunded bets get their fee back too */
+            if finished_round.tie_policy == Some(TiePolicy::Refund) {
+                ACCUMULATED_FEE.update(deps.storage, |fee| -> StdResult<_> {
+                    Ok(fee - finished_round.fee_amount.u128())
+                })?;
+            }
 
-                resp = resp.add_event(Event::new("prediction_bet").add_attributes(vec![
-                    ("round_dead", live_round.id.to_string()),
-                    ("close_price", finished_round.close_price.to_string()),
-                    (
-                        "winner",
-                        match finished_round.winner {
-                            Some(w) => w.to_string(),
-                            None => "everybody".to_string(),
-                        },
-                    ),
-                ]));
-                LIVE_ROUND.remove(deps.storage);
+            let pool = finished_round.bull_amount + finished_round.bear_amount;
+            match finished_round.tie_policy {
+                Some(TiePolicy::Refund) => {}
+                Some(TiePolicy::HouseWins) => {
+                    ACCUMULATED_FEE.update(deps.storage, |fee| -> StdResult<_> {
+                        Ok(fee + pool.u128())
+                    })?;
+                }
+                Some(TiePolicy::Rollover) => {
+                    ROLLOVER_POOL.update(deps.storage, |rollover| -> StdResult<_> {
+                        Ok(rollover + pool)
+                    })?;
+                }
+                /* The winners of this round took the rollover */
+                None => ROLLOVER_POOL.save(deps.storage, &Uint128::zero())?,
             }
+
+            resp = resp.add_event(Event::new("prediction_bet").add_attributes(vec![
+                ("round_dead", live_round.id.to_string()),
+                ("close_price", finished_round.close_price.to_string()),
+                (
+                    "winner",
+                    match finished_round.winner {
+                        Some(w) => w.to_string(),
+                        None => "everybody".to_string(),
+                    },
+                ),
+                (
+                    "tie_policy",
+                    match finished_round.tie_policy {
+                        Some(TiePolicy::Refund) => "refund".to_string(),
+                        Some(TiePolicy::HouseWins) => "house_wins".to_string(),
+                        Some(TiePolicy::Rollover) => "rollover".to_string(),
+                        None => "none".to_string(),
+                    },
+                ),
+                (
+                    "rollover_amount",
+                    finished_round.rollover_amount.to_string(),
+                ),
+                ("rake", finished_round.rake.to_string()),
+                (
+                    "close_oracle_prices",
+                    format_observations(&config, &close_price),
+                ),
+                (
+                    "close_price_source",
+                    finished_round.close_observation.source.to_string(),
+                ),
+                ("cancelled", finished_round.cancelled.to_string()),
+            ]));
+            LIVE_ROUND.remove(deps.storage);
         }
-        None => {}
     }
 
+    /* Push out the first batch of winnings, a keeper continues with DistributeWinnings */
+    resp = distribute_winnings(deps.branch(), resp, config.settlement_batch_size)?;
+
     /* Close the bidding round if it is finished
      * NOTE Don't allow two live rounds at the same time - wait for the other to close
      */
@@ -324,6 +1597,7 @@ fn execute_close_round(
                 bid_time: env.block.time,
                 close_time,
                 open_time,
+                fee_amount: Uint128::zero(),
                 id,
             },
         )?;
@@ -334,26 +1608,38 @@ fn execute_close_round(
     match &maybe_open_round {
         Some(open_round) => {
             if LIVE_ROUND.may_load(deps.storage)?.is_none() && now >= open_round.open_time {
-                let live_round = compute_round_open(deps.as_ref(), env.clone(), open_round)?;
+                let open_price = get_current_price(deps.as_ref(), now)?;
+                let live_round = compute_round_open(deps.as_ref(), &env, open_round, &open_price)?;
                 resp = resp.add_event(Event::new("prediction_bet").add_attributes(vec![
-                    ("round_bidding_close", live_round.id),
-                    ("open_price", live_round.open_price),
-                    ("bear_amount", live_round.bear_amount),
-                    ("bull_amount", live_round.bull_amount),
+                    ("round_bidding_close", live_round.id.to_string()),
+                    ("open_price", live_round.open_price.to_string()),
+                    ("bear_amount", live_round.bear_amount.to_string()),
+                    ("bull_amount", live_round.bull_amount.to_string()),
+                    (
+                        "open_oracle_prices",
+                        format_observations(&config, &open_price),
+                    ),
+                    (
+                        "open_price_source",
+                        live_round.open_observation.source.to_string(),
+                    ),
+                    ("cancelled", live_round.cancelled.to_string()),
                 ]));
                 LIVE_ROUND.save(deps.storage, &live_round)?;
                 NEXT_ROUND.remove(deps.storage);
-                let new_round_id = new_bid_round(deps, env)?;
+                let new_round_id = new_bid_round(deps.branch(), env.clone())?;
                 resp = resp.add_event(
                     Event::new("prediction_bet").add_attribute("round_bidding_open", new_round_id),
                 );
+                resp = start_strategies(deps, &env, resp, new_round_id, &config)?;
             }
         }
         None => {
-            let new_round_id = new_bid_round(deps, env)?;
+            let new_round_id = new_bid_round(deps.branch(), env.clone())?;
             resp = resp.add_event(
                 Event::new("prediction_bet").add_attribute("round_bidding_open", new_round_id),
             );
+            resp = start_strategies(deps, &env, resp, new_round_id, &config)?;
         }
     }
 
@@ -367,6 +1653,9 @@ fn execute_update_config(
     config: Config,
 ) -> Result<Response, ContractError> {
     assert_is_admin(deps.as_ref(), info, env)?;
+    assert_valid_fee_distribution(&config.fee_distribution)?;
+    assert_valid_cancel_penalty(&config)?;
+    assert_valid_oracles(deps.as_ref(), &config)?;
 
     CONFIG.save(deps.storage, &config)?;
 
@@ -386,46 +1675,159 @@ fn assert_is_current_round(deps: Deps, round_id: Uint128) -> StdResult<NextRound
     Ok(open_round)
 }
 
-fn compute_gaming_fee(deps: Deps, gross: Uint128) -> StdResult<Uint128> {
-    let staker_fee = CONFIG.load(deps.storage)?.gaming_fee;
+fn assert_bets_open(config: &Config, env: &Env, round: &NextRound) -> StdResult<()> {
+    let current_time = env.block.time.seconds();
+    /* Betting closes a little before open_time so nobody can front-run the lock */
+    let lock_time = round
+        .open_time
+        .seconds()
+        .saturating_sub(config.bet_lock_buffer_seconds.u128() as u64);
+
+    if current_time > lock_time {
+        return Err(StdError::generic_err(format!(
+            "Round {} stopped accepting bids {} second(s) ago; the next round has not yet begun",
+            round.id,
+            (current_time - lock_time)
+        )));
+    }
+
+    Ok(())
+}
+
+fn assert_cancel_window_open(
+    config: &Config,
+    env: &Env,
+    round: &NextRound,
+) -> Result<(), ContractError> {
+    let buffer = config.cancel_buffer_seconds.u128() as u64;
+
+    if env.block.time.seconds() + buffer >= round.open_time.seconds() {
+        return Err(ContractError::CancelWindowClosed {});
+    }
+
+    Ok(())
+}
+
+fn compute_cancel_penalty(config: &Config, amount: Uint128) -> StdResult<Uint128> {
+    config
+        .cancel_penalty
+        .checked_multiply_ratio(amount, FEE_PRECISION * 100)
+        .map_err(|e| StdError::generic_err(e.to_string()))
+}
+
+fn compute_gaming_fee(deps: Deps, env: &Env, player: &Addr, gross: Uint128) -> StdResult<Uint128> {
+    let staker_fee = player_fee_tier(deps, player, env.block.time)?.gaming_fee;
 
     staker_fee
         .checked_multiply_ratio(gross, FEE_PRECISION * 100)
         .map_err(|e| StdError::generic_err(e.to_string()))
 }
 
-fn compute_round_open(deps: Deps, env: Env, round: &NextRound) -> StdResult<LiveRound> {
-    /* TODO */
-    let open_price = get_current_price(deps)?;
+fn compute_referral_fee(config: &Config, staker_fee: Uint128) -> StdResult<Uint128> {
+    let referral_fee = config
+        .referral_share
+        .checked_multiply_ratio(staker_fee, FEE_PRECISION * 100)
+        .map_err(|e| StdError::generic_err(e.to_string()))?;
+
+    /* A share over 100% can't pay out more than the fee itself */
+    Ok(referral_fee.min(staker_fee))
+}
+
+fn compute_round_open(
+    deps: Deps,
+    env: &Env,
+    round: &NextRound,
+    open_price: &AggregatedPrice,
+) -> StdResult<LiveRound> {
     let config = CONFIG.load(deps.storage)?;
 
+    /* Record the scheduled open time, not the block the keeper happened to land in.
+     * A late keeper still gets a full round, and the next round a future open time */
+    let round_seconds = config.next_round_seconds.u128() as u64;
+    let close_time = round
+        .open_time
+        .plus_seconds(round_seconds)
+        .max(env.block.time.plus_seconds(round_seconds));
     Ok(LiveRound {
         id: round.id,
         bid_time: round.bid_time,
-        open_time: env.block.time,
-        close_time: env
-            .block
-            .time
-            .plus_seconds(config.next_round_seconds.u128() as u64),
-        open_price,
+        open_time: round.open_time,
+        close_time,
+        open_price: open_price.price,
         bull_amount: round.bull_amount,
         bear_amount: round.bear_amount,
+        fee_amount: round.fee_amount,
+        cancelled: open_price.deviated,
+        open_observation: price_observation(env, open_price),
     })
 }
 
-fn get_current_price(deps: Deps) -> StdResult<Uint128> {
-    let config = CONFIG.load(deps.storage)?;
+fn price_observation(env: &Env, price: &AggregatedPrice) -> PriceObservation {
+    PriceObservation {
+        source: price.source.clone(),
+        publish_time: price.publish_time,
+        conf: price.conf,
+        block_height: env.block.height,
+        block_time: env.block.time,
+    }
+}
 
-    let price: Uint128 = deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
-        contract_addr: config.fast_oracle_addr.to_string(),
-        msg: to_binary(&FastOracleQueryMsg::Price {})?,
-    }))?;
+fn get_current_price(deps: Deps, now: Timestamp) -> StdResult<AggregatedPrice> {
+    let config = CONFIG.load(deps.storage)?;
+    let observations = observe_prices(deps, &config.oracles, now);
+
+    match aggregate_price(
+        observations.clone(),
+        config.oracle_quorum,
+        config.max_oracle_deviation,
+    ) {
+        Ok(price) => Ok(price),
+        /* Without a quorum the first fallback that answers keeps the game going */
+        Err(err) => {
+            for (index, oracle) in config.fallback_oracles.iter().enumerate() {
+                if let Ok(fallback) = query_price(deps, oracle, now) {
+                    return Ok(AggregatedPrice {
+                        price: fallback.price,
+                        observations,
+                        deviated: false,
+                        source: PriceSource::Fallback {
+                            index: index as u32,
+                            addr: oracle.addr().clone(),
+                        },
+                        conf: fallback.conf,
+                        publish_time: fallback.publish_time,
+                    });
+                }
+            }
+            Err(err)
+        }
+    }
+}
 
-    Ok(price)
+/* Every oracle's answer as `addr:price`, `addr:none` when it failed */
+fn format_observations(config: &Config, price: &AggregatedPrice) -> String {
+    config
+        .oracles
+        .iter()
+        .zip(price.observations.iter())
+        .map(|(oracle, observation)| match observation {
+            Some(observation) => format!("{}:{}", oracle.addr(), observation.price),
+            None => format!("{}:none", oracle.addr()),
+        })
+        .collect::<Vec<String>>()
+        .join(",")
 }
 
-fn compute_round_close(deps: Deps, round: &LiveRound) -> StdResult<FinishedRound> {
-    let close_price = get_current_price(deps)?;
+fn compute_round_close(
+    deps: Deps,
+    env: &Env,
+    round: &LiveRound,
+    close_price: &AggregatedPrice,
+) -> StdResult<FinishedRound> {
+    /* Oracles that disagree can't decide a round, everybody gets their stake back */
+    let cancelled = round.cancelled || close_price.deviated;
+    let close_observation = price_observation(env, close_price);
+    let close_price = close_price.price;
 
     let winner = match close_price.cmp(&round.open_price) {
         std::cmp::Ordering::Greater =>
@@ -444,6 +1846,28 @@ fn compute_round_close(deps: Deps, round: &LiveRound) -> StdResult<FinishedRound
             None
         }
     };
+    let winner = if cancelled { None } else { winner };
+
+    /* Nobody can be paid from the other side when it is empty */
+    let is_void = winner.is_none()
+        || round.bull_amount == Uint128::zero()
+        || round.bear_amount == Uint128::zero();
+
+    let config = CONFIG.load(deps.storage)?;
+    let (tie_policy, rollover_amount) = if cancelled {
+        (Some(TiePolicy::Refund), Uint128::zero())
+    } else if is_void {
+        (Some(config.tie_policy), Uint128::zero())
+    } else {
+        (None, ROLLOVER_POOL.load(deps.storage)?)
+    };
+
+    /* Only decided rounds are raked, refunds stay whole */
+    let rake = match (&config.fee_mode, &tie_policy) {
+        (FeeMode::OnWinnings, None) => (round.bull_amount + round.bear_amount + rollover_amount)
+            .multiply_ratio(config.gaming_fee, FEE_PRECISION * 100),
+        _ => Uint128::zero(),
+    };
 
     Ok(FinishedRound {
         id: round.id,
@@ -455,6 +1879,17 @@ fn compute_round_close(deps: Deps, round: &LiveRound) -> StdResult<FinishedRound
         bull_amount: round.bull_amount,
         winner,
         close_price,
+        tie_policy,
+        rollover_amount,
+        fee_amount: round.fee_amount,
+        rake,
+        dust: Uint128::zero(),
+        paid_out: Uint128::zero(),
+        settled_stake: Uint128::zero(),
+        expired: false,
+        cancelled,
+        open_observation: round.open_observation.clone(),
+        close_observation,
     })
 }
 
@@ -574,3 +2009,38 @@ pub fn get_bank_transfer_to_msg(
     let transfer_bank_cosmos_msg: CosmosMsg = transfer_bank_msg.into();
     Ok(transfer_bank_cosmos_msg)
 }
+
+pub fn get_cw20_send_msg(
+    token_addr: &Addr,
+    contract: &Addr,
+    amount: Uint128,
+    msg: Binary,
+) -> StdResult<CosmosMsg> {
+    let send_cw20_msg = Cw20ExecuteMsg::Send {
+        contract: contract.into(),
+        amount,
+        msg,
+    };
+
+    let exec_cw20_send_msg = WasmMsg::Execute {
+        contract_addr: token_addr.into(),
+        msg: to_binary(&send_cw20_msg)?,
+        funds: vec![],
+    };
+
+    let cw20_send_msg: CosmosMsg = exec_cw20_send_msg.into();
+    Ok(cw20_send_msg)
+}
+
+pub fn get_cw20_burn_msg(token_addr: &Addr, amount: Uint128) -> StdResult<CosmosMsg> {
+    let burn_cw20_msg = Cw20ExecuteMsg::Burn { amount };
+
+    let exec_cw20_burn_msg = WasmMsg::Execute {
+        contract_addr: token_addr.into(),
+        msg: to_binary(&burn_cw20_msg)?,
+        funds: vec![],
+    };
+
+    let cw20_burn_msg: CosmosMsg = exec_cw20_burn_msg.into();
+    Ok(cw20_burn_msg)
+}
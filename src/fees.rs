@@ -0,0 +1,110 @@
+use cosmwasm_std::{Addr, Deps, StdResult, Storage, Timestamp, Uint128};
+
+use crate::msg::{Config, FeeTierResponse, StakeQueryMsg, StakedBalanceAtHeightResponse};
+use crate::state::{PlayerVolume, CONFIG, PLAYER_VOLUMES};
+
+/**
+ * The player's volume windows moved forward to `now`.
+ * Windows start at multiples of `volume_window_seconds`.
+ */
+fn roll_volume(config: &Config, volume: Option<PlayerVolume>, now: Timestamp) -> PlayerVolume {
+    let window = config.volume_window_seconds.u128() as u64;
+    let window_start = match window {
+        0 => Timestamp::from_seconds(0),
+        _ => Timestamp::from_seconds(now.seconds() - now.seconds() % window),
+    };
+    let empty = PlayerVolume {
+        window_start,
+        current: Uint128::zero(),
+        previous: Uint128::zero(),
+    };
+
+    match volume {
+        None => empty,
+        Some(volume) if volume.window_start == window_start => volume,
+        Some(volume) if volume.window_start.plus_seconds(window) == window_start => PlayerVolume {
+            window_start,
+            current: Uint128::zero(),
+            previous: volume.current,
+        },
+        Some(_) => empty,
+    }
+}
+
+pub fn rolling_volume(
+    storage: &dyn Storage,
+    config: &Config,
+    player: &Addr,
+    now: Timestamp,
+) -> StdResult<Uint128> {
+    let volume = roll_volume(config, PLAYER_VOLUMES.may_load(storage, player)?, now);
+    Ok(volume.current + volume.previous)
+}
+
+pub fn record_volume(
+    storage: &mut dyn Storage,
+    config: &Config,
+    player: &Addr,
+    now: Timestamp,
+    amount: Uint128,
+) -> StdResult<()> {
+    let mut volume = roll_volume(config, PLAYER_VOLUMES.may_load(storage, player)?, now);
+    volume.current += amount;
+    PLAYER_VOLUMES.save(storage, player, &volume)
+}
+
+/**
+ * Takes volume back from the player, a bet placed before the window rolled counted there
+ */
+pub fn remove_volume(
+    storage: &mut dyn Storage,
+    config: &Config,
+    player: &Addr,
+    now: Timestamp,
+    amount: Uint128,
+) -> StdResult<()> {
+    let mut volume = roll_volume(config, PLAYER_VOLUMES.may_load(storage, player)?, now);
+    let from_current = volume.current.min(amount);
+    volume.current -= from_current;
+    volume.previous = volume.previous.saturating_sub(amount - from_current);
+    PLAYER_VOLUMES.save(storage, player, &volume)
+}
+
+pub fn staked_balance(deps: Deps, config: &Config, player: &Addr) -> StdResult<Uint128> {
+    match &config.staking_addr {
+        Some(staking_addr) => {
+            let res: StakedBalanceAtHeightResponse = deps.querier.query_wasm_smart(
+                staking_addr,
+                &StakeQueryMsg::StakedBalanceAtHeight {
+                    address: player.to_string(),
+                    height: None,
+                },
+            )?;
+            Ok(res.balance)
+        }
+        None => Ok(Uint128::zero()),
+    }
+}
+
+/**
+ * The gaming fee the player pays right now, from the cheapest fee tier they qualify for
+ */
+pub fn player_fee_tier(deps: Deps, player: &Addr, now: Timestamp) -> StdResult<FeeTierResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    let rolling_volume = rolling_volume(deps.storage, &config, player, now)?;
+    let staked_balance = staked_balance(deps, &config, player)?;
+
+    let tier = config
+        .fee_tiers
+        .iter()
+        .enumerate()
+        .filter(|(_, tier)| rolling_volume >= tier.min_volume && staked_balance >= tier.min_staked)
+        .min_by_key(|(_, tier)| tier.gaming_fee);
+
+    Ok(FeeTierResponse {
+        tier: tier.map(|(index, _)| index as u32),
+        gaming_fee: tier.map_or(config.gaming_fee, |(_, tier)| tier.gaming_fee),
+        rolling_volume,
+        staked_balance,
+    })
+}
@@ -1,7 +1,12 @@
 pub mod error;
 pub mod execute;
+pub mod fees;
 pub mod msg;
+pub mod oracle;
 pub mod query;
+#[cfg(any(test, feature = "reference-oracle"))]
+pub mod reference_oracle;
+pub mod settlement;
 pub mod state;
 
 #[cfg(test)]
@@ -1,7 +1,5 @@
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::{Addr, Timestamp, Uint128};
-
-use crate::state::BetInfo;
+use cosmwasm_std::{Addr, Decimal, Timestamp, Uint128};
 
 #[cw_serde]
 pub struct InstantiateMsg {
@@ -14,7 +12,7 @@ pub enum ExecuteMsg {
      * Update part of or all of the mutable config params
      */
     UpdateConfig {
-        config: Config,
+        config: Box<Config>,
     },
     /**
      * Price go up
@@ -23,6 +21,8 @@ pub enum ExecuteMsg {
         /* In case the TX is delayed */
         round_id: Uint128,
         amount: Uint128,
+        /* Bet for another player as their approved operator */
+        player: Option<String>,
     },
     /**
      * Price go down
@@ -31,6 +31,87 @@ pub enum ExecuteMsg {
         /* In case the TX is delayed */
         round_id: Uint128,
         amount: Uint128,
+        /* Bet for another player as their approved operator */
+        player: Option<String>,
+    },
+    /**
+     * Bet on the open round with claimable winnings, the rest is paid out
+     */
+    BetFromWinnings {
+        round_id: Uint128,
+        direction: Direction,
+        amount: Uint128,
+    },
+    /**
+     * Permissionless msg to continue pushing winnings of finished rounds
+     */
+    DistributeWinnings {
+        limit: u32,
+    },
+    /**
+     * Permissionless msg to continue placing strategy bets on the round open for betting
+     */
+    RunStrategies {
+        limit: u32,
+    },
+    /**
+     * Bet on the open round from the player's balance in the contract
+     */
+    BetFromBalance {
+        round_id: Uint128,
+        direction: Direction,
+        amount: Uint128,
+        /* Bet for another player as their approved operator */
+        player: Option<String>,
+    },
+    /**
+     * Let `operator` bet up to `spend_limit` of the sender's tokens until `expires`
+     */
+    ApproveOperator {
+        operator: String,
+        spend_limit: Uint128,
+        expires: Option<Timestamp>,
+    },
+    RevokeOperator {
+        operator: String,
+    },
+    /**
+     * Register who referred the sender, can only be done once
+     */
+    RegisterReferrer {
+        referrer: String,
+    },
+    /**
+     * Send the sender's referral earnings to them
+     */
+    ClaimReferralEarnings {},
+    /**
+     * Permissionless msg to pay out the accumulated fee along the fee distribution
+     */
+    DistributeFees {},
+    /**
+     * Move tokens into the player's balance in the contract
+     */
+    Deposit {
+        amount: Uint128,
+    },
+    /**
+     * Move tokens out of the player's balance in the contract
+     */
+    Withdraw {
+        amount: Uint128,
+    },
+    /**
+     * Withdraw a bet from the open round before it locks
+     */
+    CancelBet {
+        round_id: Uint128,
+    },
+    /**
+     * Move a bet to the other side before the round locks
+     */
+    SwitchDirection {
+        round_id: Uint128,
     },
     /**
      * Permissionless msg to close the current round and open the next
@@ -39,8 +120,48 @@ pub enum ExecuteMsg {
     CloseRound {},
     /**
      * Settle winnings for an account
+     * Anyone can settle for `player` (default: sender), the payout then goes to the player.
+     * Only the player can pick another `recipient`.
+     */
+    CollectWinnings {
+        player: Option<String>,
+        recipient: Option<String>,
+    },
+    /**
+     * Send the rounding dust of settled rounds to the treasury
      */
-    CollectWinnings {},
+    SweepDust {},
+    /**
+     * Permissionless msg to forfeit what is still unclaimed in a round past its claim window,
+     * `limit` bets at a time until the round is expired
+     */
+    ExpireRound {
+        round_id: Uint128,
+        limit: u32,
+    },
+    /**
+     * Register a strategy the keeper bets for every new round, funded with `budget`
+     */
+    CreateStrategy {
+        kind: StrategyKind,
+        /* Gross amount of every bet */
+        amount: Uint128,
+        rounds: u32,
+        budget: Uint128,
+    },
+    /**
+     * Change the sender's strategy, `top_up` is added to its budget
+     */
+    ModifyStrategy {
+        kind: Option<StrategyKind>,
+        amount: Option<Uint128>,
+        rounds: Option<u32>,
+        top_up: Option<Uint128>,
+    },
+    /**
+     * Stop the sender's strategy and refund what is left of its budget
+     */
+    CancelStrategy {},
     Hault {},
     Resume {},
 }
@@ -67,6 +188,34 @@ pub enum QueryMsg {
     MyPendingReward {
         player: Addr,
     },
+    ClaimableRounds {
+        player: Addr,
+    },
+    Solvency {},
+    ExpiringRounds {
+        player: Addr,
+        within_seconds: u64,
+    },
+    Balance {
+        player: Addr,
+    },
+    PendingSettlements {},
+    Strategy {
+        player: Addr,
+    },
+    Operators {
+        player: Addr,
+    },
+    Referral {
+        address: Addr,
+    },
+    FeeTier {
+        player: Addr,
+    },
+    /**
+     * Asks every oracle and fallback for its price right now
+     */
+    OracleHealth {},
 }
 
 #[cw_serde]
@@ -78,13 +227,12 @@ pub enum Direction {
     Bear,
 }
 
-impl ToString for Direction {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Direction::Bull => "bull",
-            Direction::Bear => "bear",
+            Direction::Bull => write!(f, "bull"),
+            Direction::Bear => write!(f, "bear"),
         }
-        .to_string()
     }
 }
 
@@ -108,6 +256,98 @@ pub struct MyCurrentPositionResponse {
     pub next_bull_amount: Uint128,
 }
 
+#[cw_serde]
+pub struct ClaimableRound {
+    pub round_id: Uint128,
+    pub direction: Direction,
+    pub amount: Uint128,
+    pub payout: Uint128,
+}
+
+#[cw_serde]
+pub struct ClaimableRoundsResponse {
+    pub claimable_rounds: Vec<ClaimableRound>,
+    pub pending_reward: Uint128,
+}
+
+#[cw_serde]
+pub struct ExpiringRound {
+    pub round_id: Uint128,
+    pub payout: Uint128,
+    pub expires_at: Timestamp,
+}
+
+#[cw_serde]
+pub struct ExpiringRoundsResponse {
+    pub expiring_rounds: Vec<ExpiringRound>,
+}
+
+#[cw_serde]
+pub struct PendingSettlement {
+    pub round_id: Uint128,
+    pub last_player: Option<Addr>,
+}
+
+#[cw_serde]
+pub struct PendingSettlementsResponse {
+    pub pending_settlements: Vec<PendingSettlement>,
+}
+
+#[cw_serde]
+pub struct Operator {
+    pub operator: Addr,
+    pub spend_limit: Uint128,
+    pub expires: Option<Timestamp>,
+}
+
+#[cw_serde]
+pub struct OperatorsResponse {
+    pub operators: Vec<Operator>,
+}
+
+#[cw_serde]
+pub struct FeeTierResponse {
+    /* Index into the config's fee tiers, none when the flat gaming_fee applies */
+    pub tier: Option<u32>,
+    pub gaming_fee: Uint128,
+    pub rolling_volume: Uint128,
+    pub staked_balance: Uint128,
+}
+
+#[cw_serde]
+pub struct OracleHealth {
+    pub addr: Addr,
+    /* Normalized price, none when the oracle failed to answer */
+    pub price: Option<Uint128>,
+    pub publish_time: Option<Timestamp>,
+    /* Why the oracle failed to answer */
+    pub error: Option<String>,
+}
+
+#[cw_serde]
+pub struct OracleHealthResponse {
+    pub oracles: Vec<OracleHealth>,
+    pub fallback_oracles: Vec<OracleHealth>,
+    /* Enough oracles answer for the median to be used */
+    pub quorum_met: bool,
+}
+
+#[cw_serde]
+pub struct SolvencyResponse {
+    pub token_balance: Uint128,
+    pub unclaimed_winnings: Uint128,
+    pub open_stakes: Uint128,
+    pub accumulated_fee: Uint128,
+    pub dust: Uint128,
+    pub rollover_pool: Uint128,
+    pub player_balances: Uint128,
+    pub strategy_budgets: Uint128,
+    pub referral_earnings: Uint128,
+    /* Sum of everything above except the balance */
+    pub liabilities: Uint128,
+    pub is_solvent: bool,
+}
+
 #[cw_serde]
 /**
  * Parameters which are mutable by a governance vote
@@ -115,12 +355,190 @@ pub struct MyCurrentPositionResponse {
 pub struct Config {
     /* After a round ends this is the duration of the next */
     pub next_round_seconds: Uint128,
-    pub fast_oracle_addr: Addr,
+    /* Where round open and close prices come from, the median of those answering is used */
+    pub oracles: Vec<OracleSource>,
+    /* How many oracles have to answer for a price to be used */
+    pub oracle_quorum: u32,
+    /* Rounds are cancelled when an oracle strays further from the median, same precision as gaming_fee */
+    pub max_oracle_deviation: Uint128,
+    /* Tried in order when too few of the oracles answer, the first answer is used */
+    pub fallback_oracles: Vec<OracleSource>,
     pub minimum_bet: Uint128,
     pub burn_fee: Uint128,
     pub gaming_fee: Uint128,
     pub token_addr: Addr,
+    /* Kept from a cancelled or switched stake, same precision as gaming_fee */
+    pub cancel_penalty: Uint128,
+    /* Cancelling and switching close this many seconds before open_time */
+    pub cancel_buffer_seconds: Uint128,
+    /* Largest gross amount accepted in a single bet */
+    pub maximum_bet: Option<Uint128>,
+    /* Largest net amount one player can stake in a single round */
+    pub maximum_player_round_bet: Option<Uint128>,
+    /* Largest allowed ratio of one side's pool to the other's */
+    pub max_pool_ratio: Option<Decimal>,
+    /* Betting closes this many seconds before open_time */
+    pub bet_lock_buffer_seconds: Uint128,
+    /* How rounds without a winner (a tie or an empty side) are settled */
+    pub tie_policy: TiePolicy,
+    /* Receives swept dust */
+    pub treasury_addr: Addr,
+    /* Winnings unclaimed this long after close_time can be expired */
+    pub claim_window_seconds: Option<Uint128>,
+    /* Where expired winnings go */
+    pub expiry_destination: ExpiryDestination,
+    /* Whether winnings are pushed to players after a round closes */
+    pub push_settlement: PushSettlement,
+    /* Bets handled per CloseRound or DistributeWinnings call.
+     * 0 pushes nothing, players collect their winnings themselves */
+    pub settlement_batch_size: u32,
+    /* Strategies run per CloseRound call, a keeper continues with RunStrategies */
+    pub strategy_batch_size: u32,
+    /* Part of a referred bettor's gaming fee paid to the referrer, same precision as gaming_fee.
+     * Only fees taken from stakes are shared, a rake has no bettor to credit */
+    pub referral_share: Uint128,
+    /* How DistributeFees splits the accumulated fee */
+    pub fee_distribution: FeeDistribution,
+    /* stake_cw20_external_rewards contract funded with the staker share */
+    pub staking_rewards_addr: Option<Addr>,
+    /* Cheaper gaming fees for players with enough volume or stake, the cheapest match applies.
+     * Only fees taken from stakes get cheaper, the rake is always gaming_fee */
+    pub fee_tiers: Vec<FeeTier>,
+    /* Length of the windows player volume is counted in, 0 counts all volume ever */
+    pub volume_window_seconds: Uint128,
+    /* stake_cw20 contract the staked balances for fee tiers come from */
+    pub staking_addr: Option<Addr>,
+    /* Whether the gaming fee is taken from every stake or raked from decided pools */
+    pub fee_mode: FeeMode,
+}
+
+#[cw_serde]
+pub enum OracleSource {
+    /* Answers `Price {}` with a bare integer that has `decimals` decimals */
+    FastOracle {
+        addr: Addr,
+        decimals: u32,
+    },
+    /* Pyth-style price feed with an exponent, confidence and publish time */
+    Pyth {
+        addr: Addr,
+        price_feed_id: String,
+    },
+    /* Band-style standard reference data */
+    Band {
+        addr: Addr,
+        base_symbol: String,
+        quote_symbol: String,
+    },
+    /**
+     * Price of token1 in token2 from a wasmswap pool's reserves, in raw token units.
+     * With a window the price is the pool's TWAP over it, sampled at every CloseRound.
+     */
+    Wasmswap {
+        addr: Addr,
+        twap_window_seconds: Option<u64>,
+    },
+}
+
+/* Which oracles a round's price came from */
+#[cw_serde]
+#[derive(Default)]
+pub enum PriceSource {
+    /* The median of the configured oracles */
+    #[default]
+    Primary,
+    /* The fallback oracle at this index */
+    Fallback {
+        index: u32,
+        addr: Addr,
+    },
+}
+
+impl std::fmt::Display for PriceSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PriceSource::Primary => write!(f, "primary"),
+            PriceSource::Fallback { index, addr } => write!(f, "fallback:{}:{}", index, addr),
+        }
+    }
+}
+
+impl OracleSource {
+    pub fn addr(&self) -> &Addr {
+        match self {
+            OracleSource::FastOracle { addr, .. } => addr,
+            OracleSource::Pyth { addr, .. } => addr,
+            OracleSource::Band { addr, .. } => addr,
+            OracleSource::Wasmswap { addr, .. } => addr,
+        }
+    }
+}
+
+#[cw_serde]
+pub enum FeeMode {
+    /* Deducted from the gross amount of every bet */
+    OnStake,
+    /* Raked from the pool of rounds with a winner at close, refunds and losers pay nothing.
+     * The rake is the flat gaming_fee: fee tiers and referral shares don't apply */
+    OnWinnings,
 }
+
+/**
+ * A player needs both minimums, volume is counted over the current and the previous window
+ */
+#[cw_serde]
+pub struct FeeTier {
+    pub min_volume: Uint128,
+    pub min_staked: Uint128,
+    pub gaming_fee: Uint128,
+}
+
+/**
+ * Shares of the accumulated fee, same precision as gaming_fee and adding up to 100%
+ * Without a staking rewards contract the staker share goes to the treasury
+ */
+#[cw_serde]
+pub struct FeeDistribution {
+    pub staker_share: Uint128,
+    pub treasury_share: Uint128,
+    pub burn_share: Uint128,
+}
+
+#[cw_serde]
+pub enum StrategyKind {
+    /* Always the same side */
+    Fixed { direction: Direction },
+    /* The side that won the last finished round, skipped after a round without a winner */
+    FollowWinner {},
+}
+
+#[cw_serde]
+pub enum PushSettlement {
+    /* Players collect their winnings, except balance-funded bets which are always credited */
+    Off,
+    /* Winnings are credited to the players' balances */
+    Credit,
+    /* Winnings are transferred to the players */
+    Transfer,
+}
+
+#[cw_serde]
+pub enum ExpiryDestination {
+    Treasury,
+    /* Into the rollover pool for the winners of the next decided round */
+    Jackpot,
+}
+
+#[cw_serde]
+pub enum TiePolicy {
+    /* Every bet gets its stake back */
+    Refund,
+    /* The whole pool goes to the accumulated fee */
+    HouseWins,
+    /* The whole pool is added to the next round that has a winner */
+    Rollover,
+}
+/* Rounds stored by the first version lack the fields marked default */
 #[cw_serde]
 pub struct NextRound {
     pub id: Uint128,
@@ -129,6 +547,9 @@ pub struct NextRound {
     pub close_time: Timestamp,
     pub bull_amount: Uint128,
     pub bear_amount: Uint128,
+    /* Fees booked on the round's bets, given back if the round is refunded */
+    #[serde(default)]
+    pub fee_amount: Uint128,
 }
 
 #[cw_serde]
@@ -140,6 +561,15 @@ pub struct LiveRound {
     pub open_price: Uint128,
     pub bull_amount: Uint128,
     pub bear_amount: Uint128,
+    /* Fees booked on the round's bets, given back if the round is refunded */
+    #[serde(default)]
+    pub fee_amount: Uint128,
+    /* The oracles disagreed on the open price, the round is refunded when it closes */
+    #[serde(default)]
+    pub cancelled: bool,
+    /* Where the open price came from, taken when betting locked */
+    #[serde(default)]
+    pub open_observation: PriceObservation,
 }
 
 #[cw_serde]
@@ -153,18 +583,123 @@ pub struct FinishedRound {
     pub winner: Option<Direction>,
     pub bull_amount: Uint128,
     pub bear_amount: Uint128,
+    /* Set when the round had no winner, to the policy that settled it */
+    pub tie_policy: Option<TiePolicy>,
+    /* Pool rolled over from earlier rounds, paid out to this round's winners */
+    #[serde(default)]
+    pub rollover_amount: Uint128,
+    /* Fees booked on the round's bets, given back when the round is refunded */
+    #[serde(default)]
+    pub fee_amount: Uint128,
+    /* Taken from the pool as the gaming fee when the fee is charged on winnings */
+    #[serde(default)]
+    pub rake: Uint128,
+    /* Left in the pool because payouts round down, booked once every winner is settled */
+    #[serde(default)]
+    pub dust: Uint128,
+    /* Paid out to the winners settled so far */
+    #[serde(default)]
+    pub paid_out: Uint128,
+    /* Winning stake settled so far */
+    #[serde(default)]
+    pub settled_stake: Uint128,
+    /* Unclaimed winnings were forfeited after the claim window */
+    #[serde(default)]
+    pub expired: bool,
+    /* The oracles disagreed on the open or close price and every bet was refunded */
+    #[serde(default)]
+    pub cancelled: bool,
+    #[serde(default)]
+    pub open_observation: PriceObservation,
+    #[serde(default)]
+    pub close_observation: PriceObservation,
+}
+
+/**
+ * How a round's open or close price was observed, kept for dispute resolution
+ */
+#[cw_serde]
+#[derive(Default)]
+pub struct PriceObservation {
+    pub source: PriceSource,
+    /* When the oracle last updated the price, the oldest answer's for a median */
+    pub publish_time: Option<Timestamp>,
+    /* Confidence interval on the price's scale, zero when the oracle has none */
+    pub conf: Uint128,
+    /* Block the price was read in */
+    pub block_height: u64,
+    pub block_time: Timestamp,
 }
 
 #[cw_serde]
 pub enum FastOracleQueryMsg {
+    /* The latest price as a bare integer, all the game reads */
     Price {},
+    /* The latest price with when and by whom it was fed */
+    PriceData {},
+    /* Fed prices newest first */
+    History {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    Config {},
 }
 
 #[cw_serde]
 pub enum FastOracleExecuteMsg {
     Update { price: Uint128 },
+    /* Hands the oracle over to a new owner */
     Owner { owner: Addr },
+    AddFeeder { feeder: Addr },
+    RemoveFeeder { feeder: Addr },
+}
+
+#[cw_serde]
+pub struct FastOracleInstantiateMsg {
+    /* Defaults to the instantiator */
+    pub owner: Option<Addr>,
+    pub feeders: Vec<Addr>,
+    /* Price to start from, queries fail until one is fed otherwise */
+    pub price: Option<Uint128>,
+}
+
+#[cw_serde]
+pub struct FastOraclePriceData {
+    /* Position in the history */
+    pub id: u64,
+    pub price: Uint128,
+    pub feeder: Addr,
+    pub updated_at: Timestamp,
+    pub height: u64,
+}
+
+#[cw_serde]
+pub struct FastOracleHistoryResponse {
+    pub prices: Vec<FastOraclePriceData>,
 }
 
 #[cw_serde]
-pub struct FastOracleInstantiateMsg {}
+pub struct FastOracleConfigResponse {
+    pub owner: Addr,
+    pub feeders: Vec<Addr>,
+}
+
+#[cw_serde]
+pub enum StakeQueryMsg {
+    StakedBalanceAtHeight {
+        address: String,
+        height: Option<u64>,
+    },
+}
+
+#[cw_serde]
+pub struct StakedBalanceAtHeightResponse {
+    pub balance: Uint128,
+    pub height: u64,
+}
+
+/* Receive msg of stake_cw20_external_rewards */
+#[cw_serde]
+pub enum StakingRewardsReceiveMsg {
+    Fund {},
+}
@@ -0,0 +1,400 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+    Addr, Decimal, Deps, DepsMut, Order, StdError, StdResult, Timestamp, Uint128, Uint256, Uint64,
+};
+use cw20::Denom;
+use cw_storage_plus::Bound;
+use std::convert::TryFrom;
+
+use crate::msg::{FastOraclePriceData, FastOracleQueryMsg, OracleSource, PriceSource};
+use crate::state::{TwapObservation, TWAP_OBSERVATIONS};
+
+/* Decimals of every price the game works with, whatever the source reports */
+pub const PRICE_DECIMALS: u32 = 8;
+/* Band reports rates with 18 decimals */
+const BAND_DECIMALS: u32 = 18;
+
+/**
+ * A price read from any oracle source, normalized to PRICE_DECIMALS
+ */
+#[cw_serde]
+pub struct OraclePrice {
+    pub price: Uint128,
+    /* Confidence interval on the same scale, zero when the source has none */
+    pub conf: Uint128,
+    /* When the source last updated the price, if it says */
+    pub publish_time: Option<Timestamp>,
+}
+
+/**
+ * Median of the oracles that answered, with every answer kept for the round events
+ */
+#[cw_serde]
+pub struct AggregatedPrice {
+    pub price: Uint128,
+    /* One per oracle in config order, None when it failed to answer */
+    pub observations: Vec<Option<OraclePrice>>,
+    /* Some answer strayed further from the median than allowed */
+    pub deviated: bool,
+    pub source: PriceSource,
+    /* The widest confidence interval of the answers used */
+    pub conf: Uint128,
+    /* The oldest publish time of the answers used that have one */
+    pub publish_time: Option<Timestamp>,
+}
+
+#[cw_serde]
+pub enum PythQueryMsg {
+    PriceFeed { id: String },
+}
+
+#[cw_serde]
+pub struct PythPriceFeedResponse {
+    pub price_feed: PythPriceFeed,
+}
+
+#[cw_serde]
+pub struct PythPriceFeed {
+    pub id: String,
+    pub price: PythPrice,
+    pub ema_price: PythPrice,
+}
+
+/* `price * 10^expo`, the price is a signed integer sent as a string */
+#[cw_serde]
+pub struct PythPrice {
+    pub price: String,
+    pub conf: Uint64,
+    pub expo: i32,
+    pub publish_time: i64,
+}
+
+#[cw_serde]
+pub enum BandQueryMsg {
+    GetReferenceData {
+        base_symbol: String,
+        quote_symbol: String,
+    },
+}
+
+#[cw_serde]
+pub struct BandReferenceData {
+    pub rate: Uint256,
+    pub last_updated_base: u64,
+    pub last_updated_quote: u64,
+}
+
+#[cw_serde]
+pub enum WasmswapQueryMsg {
+    Info {},
+}
+
+#[cw_serde]
+pub struct WasmswapInfoResponse {
+    pub token1_reserve: Uint128,
+    pub token1_denom: Denom,
+    pub token2_reserve: Uint128,
+    pub token2_denom: Denom,
+    pub lp_token_supply: Uint128,
+    pub lp_token_address: String,
+    pub owner: Option<String>,
+    pub lp_fee_percent: Decimal,
+    pub protocol_fee_percent: Decimal,
+    pub protocol_fee_recipient: String,
+}
+
+/* One answer per source, None when it failed to answer */
+pub fn observe_prices(
+    deps: Deps,
+    sources: &[OracleSource],
+    now: Timestamp,
+) -> Vec<Option<OraclePrice>> {
+    sources
+        .iter()
+        .map(|source| query_price(deps, source, now).ok())
+        .collect()
+}
+
+/**
+ * Median of the observed answers, erroring without a quorum.
+ * max_deviation is in basis points of the median.
+ */
+pub fn aggregate_price(
+    observations: Vec<Option<OraclePrice>>,
+    quorum: u32,
+    max_deviation: Uint128,
+) -> StdResult<AggregatedPrice> {
+    let mut prices: Vec<Uint128> = observations
+        .iter()
+        .flatten()
+        .map(|observation| observation.price)
+        .collect();
+    if prices.is_empty() || prices.len() < quorum as usize {
+        return Err(StdError::generic_err(format!(
+            "Only {} of {} oracles answered, {} needed",
+            prices.len(),
+            observations.len(),
+            quorum
+        )));
+    }
+
+    prices.sort();
+    let middle = prices.len() / 2;
+    /* is_multiple_of needs a newer toolchain than the optimizer image ships */
+    #[allow(clippy::manual_is_multiple_of)]
+    let price = if prices.len() % 2 == 0 {
+        (prices[middle - 1] + prices[middle]) / Uint128::new(2)
+    } else {
+        prices[middle]
+    };
+
+    /* diff / price > max_deviation / 10000, multiplied out so a zero median can't divide */
+    let deviated = prices.iter().any(|p| {
+        let diff = if *p > price { *p - price } else { price - *p };
+        Uint256::from(diff) * Uint256::from(10_000u128)
+            > Uint256::from(max_deviation) * Uint256::from(price)
+    });
+
+    let answers = observations.iter().flatten();
+    let conf = answers
+        .clone()
+        .map(|observation| observation.conf)
+        .max()
+        .unwrap_or_default();
+    let publish_time = answers
+        .filter_map(|observation| observation.publish_time)
+        .min();
+
+    Ok(AggregatedPrice {
+        price,
+        observations,
+        deviated,
+        source: PriceSource::Primary,
+        conf,
+        publish_time,
+    })
+}
+
+pub fn query_price(deps: Deps, source: &OracleSource, now: Timestamp) -> StdResult<OraclePrice> {
+    match source {
+        OracleSource::FastOracle { addr, decimals } => {
+            /* Oracles that keep the feed time answer PriceData, bare ones only Price */
+            let (price, publish_time) = match deps
+                .querier
+                .query_wasm_smart::<FastOraclePriceData>(addr, &FastOracleQueryMsg::PriceData {})
+            {
+                Ok(data) => (data.price, Some(data.updated_at)),
+                Err(_) => (
+                    deps.querier
+                        .query_wasm_smart(addr, &FastOracleQueryMsg::Price {})?,
+                    None,
+                ),
+            };
+
+            Ok(OraclePrice {
+                price: normalize(price.into(), *decimals)?,
+                conf: Uint128::zero(),
+                publish_time,
+            })
+        }
+        OracleSource::Pyth {
+            addr,
+            price_feed_id,
+        } => {
+            let res: PythPriceFeedResponse = deps.querier.query_wasm_smart(
+                addr,
+                &PythQueryMsg::PriceFeed {
+                    id: price_feed_id.clone(),
+                },
+            )?;
+            let pyth_price = res.price_feed.price;
+
+            let price = pyth_price
+                .price
+                .parse::<u64>()
+                .map_err(|_| StdError::generic_err("Pyth price is not a positive integer"))?;
+            let publish_time = u64::try_from(pyth_price.publish_time)
+                .map_err(|_| StdError::generic_err("Pyth publish time is before 1970"))?;
+            /* A positive exponent scales the integer up, a negative one gives it decimals */
+            let (scale, decimals) = match u32::try_from(pyth_price.expo) {
+                Ok(expo) => (pow10(expo)?, 0),
+                Err(_) => (Uint256::one(), pyth_price.expo.unsigned_abs()),
+            };
+
+            Ok(OraclePrice {
+                price: normalize(checked_scale(Uint256::from(price), scale)?, decimals)?,
+                conf: normalize(
+                    checked_scale(Uint256::from(pyth_price.conf.u64()), scale)?,
+                    decimals,
+                )?,
+                publish_time: Some(Timestamp::from_seconds(publish_time)),
+            })
+        }
+        OracleSource::Band {
+            addr,
+            base_symbol,
+            quote_symbol,
+        } => {
+            let res: BandReferenceData = deps.querier.query_wasm_smart(
+                addr,
+                &BandQueryMsg::GetReferenceData {
+                    base_symbol: base_symbol.clone(),
+                    quote_symbol: quote_symbol.clone(),
+                },
+            )?;
+
+            Ok(OraclePrice {
+                price: normalize(res.rate, BAND_DECIMALS)?,
+                conf: Uint128::zero(),
+                /* The rate is only as fresh as the older of its two legs */
+                publish_time: Some(Timestamp::from_seconds(
+                    res.last_updated_base.min(res.last_updated_quote),
+                )),
+            })
+        }
+        OracleSource::Wasmswap {
+            addr,
+            twap_window_seconds,
+        } => {
+            let price = match twap_window_seconds {
+                Some(window) => query_twap(deps, addr, *window, now)?,
+                None => query_spot_price(deps, addr)?,
+            };
+
+            Ok(OraclePrice {
+                price,
+                conf: Uint128::zero(),
+                publish_time: None,
+            })
+        }
+    }
+}
+
+fn query_spot_price(deps: Deps, pool_addr: &Addr) -> StdResult<Uint128> {
+    let info: WasmswapInfoResponse = deps
+        .querier
+        .query_wasm_smart(pool_addr, &WasmswapQueryMsg::Info {})?;
+    if info.token1_reserve.is_zero() {
+        return Err(StdError::generic_err("The wasmswap pool is empty"));
+    }
+
+    Ok(info
+        .token2_reserve
+        .multiply_ratio(10u128.pow(PRICE_DECIMALS), info.token1_reserve))
+}
+
+/**
+ * Average of the sampled spot prices between the last sample and the latest one at least
+ * `window` seconds before it. Before there are two samples the spot price is used.
+ * Without a sample from this block the pool has to still answer, a dead pool has no TWAP.
+ */
+fn query_twap(deps: Deps, pool_addr: &Addr, window: u64, now: Timestamp) -> StdResult<Uint128> {
+    let observations = TWAP_OBSERVATIONS.prefix(pool_addr);
+    let latest = observations
+        .range(deps.storage, None, None, Order::Descending)
+        .next()
+        .transpose()?;
+    let (latest_time, latest) = match latest {
+        Some(latest) => latest,
+        None => return query_spot_price(deps, pool_addr),
+    };
+    if latest_time < now.seconds() {
+        query_spot_price(deps, pool_addr)?;
+    }
+
+    /* The newest sample old enough to cover the window, or else the oldest one there is */
+    let window_start = latest_time.saturating_sub(window);
+    let reference = match observations
+        .range(
+            deps.storage,
+            None,
+            Some(Bound::inclusive(window_start)),
+            Order::Descending,
+        )
+        .next()
+    {
+        Some(reference) => Some(reference?),
+        None => observations
+            .range(deps.storage, None, None, Order::Ascending)
+            .next()
+            .transpose()?,
+    };
+
+    match reference {
+        Some((reference_time, reference)) if reference_time < latest_time => {
+            Ok((latest.cumulative - reference.cumulative)
+                / Uint128::from(latest_time - reference_time))
+        }
+        _ => query_spot_price(deps, pool_addr),
+    }
+}
+
+/**
+ * Add a sample of the pool's spot price to the TWAP observations.
+ * Prices between two samples are taken to move linearly from one to the other.
+ */
+pub fn sample_twap(deps: DepsMut, pool_addr: &Addr, window: u64, now: Timestamp) -> StdResult<()> {
+    let price = query_spot_price(deps.as_ref(), pool_addr)?;
+    let now = now.seconds();
+
+    let observations = TWAP_OBSERVATIONS.prefix(pool_addr);
+    let last = observations
+        .range(deps.storage, None, None, Order::Descending)
+        .next()
+        .transpose()?;
+    let cumulative = match last {
+        Some((last_time, last)) if last_time < now => {
+            last.cumulative + (last.price + price).multiply_ratio(now - last_time, 2u128)
+        }
+        /* Already sampled in this block */
+        Some(_) => return Ok(()),
+        None => Uint128::zero(),
+    };
+    TWAP_OBSERVATIONS.save(
+        deps.storage,
+        (pool_addr, now),
+        &TwapObservation { price, cumulative },
+    )?;
+
+    /* Of the samples before the window only the newest is still needed */
+    let stale = observations
+        .keys(
+            deps.storage,
+            None,
+            Some(Bound::inclusive(now.saturating_sub(window))),
+            Order::Descending,
+        )
+        .skip(1)
+        .collect::<StdResult<Vec<_>>>()?;
+    for time in stale {
+        TWAP_OBSERVATIONS.remove(deps.storage, (pool_addr, time));
+    }
+
+    Ok(())
+}
+
+/**
+ * Rescale a value with `decimals` decimals to PRICE_DECIMALS, extra precision is cut off
+ */
+fn normalize(value: Uint256, decimals: u32) -> StdResult<Uint128> {
+    let normalized = if decimals >= PRICE_DECIMALS {
+        value / pow10(decimals - PRICE_DECIMALS)?
+    } else {
+        checked_scale(value, pow10(PRICE_DECIMALS - decimals)?)?
+    };
+
+    Uint128::try_from(normalized).map_err(|e| StdError::generic_err(e.to_string()))
+}
+
+/* Oracles choose their own exponents, an absurd one is an error rather than a panic */
+fn pow10(exp: u32) -> StdResult<Uint256> {
+    Uint256::from(10u128)
+        .checked_pow(exp)
+        .map_err(|e| StdError::generic_err(e.to_string()))
+}
+
+fn checked_scale(value: Uint256, scale: Uint256) -> StdResult<Uint256> {
+    value
+        .checked_mul(scale)
+        .map_err(|e| StdError::generic_err(e.to_string()))
+}
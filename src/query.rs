@@ -1,25 +1,39 @@
 use cosmwasm_std::{to_binary, Addr, Binary, Deps, Env, Order, StdResult, Timestamp, Uint128};
+use cw20::{BalanceResponse, Cw20QueryMsg};
 use cw_storage_plus::Bound;
 
 use crate::{
+    fees::player_fee_tier,
     msg::{
-        ConfigResponse, Direction, FinishedRound, MyCurrentPositionResponse, QueryMsg,
-        StatusResponse,
+        ClaimableRoundsResponse, ConfigResponse, Direction, ExpiringRound, ExpiringRoundsResponse,
+        FinishedRound, MyCurrentPositionResponse, Operator, OperatorsResponse, OracleHealth,
+        OracleHealthResponse, OracleSource, PendingSettlement, PendingSettlementsResponse,
+        PriceObservation, PriceSource, QueryMsg, SolvencyResponse, StatusResponse, TiePolicy,
+    },
+    oracle::query_price,
+    settlement::{
+        compute_bet_payout, resolve_referrals, round_expires_at, settle_player, unbooked_dust,
     },
     state::{
-        bet_info_key, bet_info_storage, MyGameResponse, PendingRewardResponse, CONFIG, LIVE_ROUND,
-        NEXT_ROUND, NEXT_ROUND_ID, ROUNDS, TOTAL_VOLUME,
+        bet_info_key, bet_info_storage, MyGameResponse, PendingRewardResponse,
+        PlayerBalanceResponse, ReferralResponse, StrategyResponse, ACCUMULATED_FEE, BALANCES,
+        CONFIG, DUST, LIVE_ROUND, NEXT_ROUND, NEXT_ROUND_ID, OPERATORS, PENDING_REFERRALS,
+        PENDING_SETTLEMENTS, REFERRAL_COUNTS, REFERRAL_EARNINGS, REFERRERS, ROLLOVER_POOL, ROUNDS,
+        STRATEGIES, TOTAL_VOLUME,
     },
 };
 
-#[cfg(not(feature = "library"))]
+#[cfg(not(any(feature = "library", feature = "reference-oracle")))]
 use cosmwasm_std::entry_point;
 
 // Query limits
 const DEFAULT_QUERY_LIMIT: u32 = 10;
 const MAX_QUERY_LIMIT: u32 = 30;
 
-#[cfg_attr(not(feature = "library"), entry_point)]
+#[cfg_attr(
+    not(any(feature = "library", feature = "reference-oracle")),
+    entry_point
+)]
 pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::Config {} => to_binary(&query_config(deps)?),
@@ -34,6 +48,19 @@ pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
             limit,
         } => to_binary(&query_my_games(deps, player, start_after, limit)?),
         QueryMsg::MyPendingReward { player } => to_binary(&query_my_pending_reward(deps, player)?),
+        QueryMsg::ClaimableRounds { player } => to_binary(&query_claimable_rounds(deps, player)?),
+        QueryMsg::Solvency {} => to_binary(&query_solvency(deps, env)?),
+        QueryMsg::Balance { player } => to_binary(&query_balance(deps, player)?),
+        QueryMsg::PendingSettlements {} => to_binary(&query_pending_settlements(deps)?),
+        QueryMsg::Strategy { player } => to_binary(&query_strategy(deps, player)?),
+        QueryMsg::Operators { player } => to_binary(&query_operators(deps, player)?),
+        QueryMsg::Referral { address } => to_binary(&query_referral(deps, address)?),
+        QueryMsg::FeeTier { player } => to_binary(&player_fee_tier(deps, &player, env.block.time)?),
+        QueryMsg::OracleHealth {} => to_binary(&query_oracle_health(deps, env)?),
+        QueryMsg::ExpiringRounds {
+            player,
+            within_seconds,
+        } => to_binary(&query_expiring_rounds(deps, env, player, within_seconds)?),
         QueryMsg::ReverseMyGameList {
             player,
             start_after,
@@ -42,6 +69,40 @@ pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     }
 }
 
+fn query_oracle_health(deps: Deps, env: Env) -> StdResult<OracleHealthResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    let health = |oracles: &[OracleSource]| -> Vec<OracleHealth> {
+        oracles
+            .iter()
+            .map(|oracle| match query_price(deps, oracle, env.block.time) {
+                Ok(price) => OracleHealth {
+                    addr: oracle.addr().clone(),
+                    price: Some(price.price),
+                    publish_time: price.publish_time,
+                    error: None,
+                },
+                Err(err) => OracleHealth {
+                    addr: oracle.addr().clone(),
+                    price: None,
+                    publish_time: None,
+                    error: Some(err.to_string()),
+                },
+            })
+            .collect()
+    };
+
+    let oracles = health(&config.oracles);
+    let answered = oracles
+        .iter()
+        .filter(|oracle| oracle.price.is_some())
+        .count();
+    Ok(OracleHealthResponse {
+        quorum_met: answered > 0 && answered >= config.oracle_quorum as usize,
+        oracles,
+        fallback_oracles: health(&config.fallback_oracles),
+    })
+}
+
 fn query_finished_round(deps: Deps, round_id: Uint128) -> StdResult<FinishedRound> {
     let round = ROUNDS.may_load(deps.storage, round_id.u128())?;
     match round {
@@ -56,10 +117,31 @@ fn query_finished_round(deps: Deps, round_id: Uint128) -> StdResult<FinishedRoun
             winner: Some(Direction::Bear),
             bull_amount: Uint128::zero(),
             bear_amount: Uint128::zero(),
+            tie_policy: None,
+            rollover_amount: Uint128::zero(),
+            fee_amount: Uint128::zero(),
+            rake: Uint128::zero(),
+            dust: Uint128::zero(),
+            paid_out: Uint128::zero(),
+            settled_stake: Uint128::zero(),
+            expired: false,
+            cancelled: false,
+            open_observation: empty_observation(),
+            close_observation: empty_observation(),
         }),
     }
 }
 
+fn empty_observation() -> PriceObservation {
+    PriceObservation {
+        source: PriceSource::Primary,
+        publish_time: None,
+        conf: Uint128::zero(),
+        block_height: 0,
+        block_time: Timestamp::from_nanos(0),
+    }
+}
+
 fn query_my_current_position(deps: Deps, address: String) -> StdResult<MyCurrentPositionResponse> {
     let round_id = NEXT_ROUND_ID.load(deps.storage)?;
     let next_bet_key = (round_id - 1, deps.api.addr_validate(&address)?);
@@ -69,16 +151,15 @@ fn query_my_current_position(deps: Deps, address: String) -> StdResult<MyCurrent
     let mut next_bull_amount = Uint128::zero();
     let mut next_bear_amount = Uint128::zero();
 
-    match next_bet_info {
-        Some(bet_info) => match bet_info.direction {
+    if let Some(bet_info) = next_bet_info {
+        match bet_info.direction {
             Direction::Bull => {
                 next_bull_amount = bet_info.amount;
             }
             Direction::Bear => {
                 next_bear_amount = bet_info.amount;
             }
-        },
-        None => {}
+        }
     }
 
     let mut live_bull_amount: Uint128 = Uint128::zero();
@@ -86,16 +167,15 @@ fn query_my_current_position(deps: Deps, address: String) -> StdResult<MyCurrent
     if round_id > 1 {
         let live_bet_key = (round_id - 2, deps.api.addr_validate(&address)?);
         let live_bet_info = bet_info_storage().may_load(deps.storage, live_bet_key)?;
-        match live_bet_info {
-            Some(bet_info) => match bet_info.direction {
+        if let Some(bet_info) = live_bet_info {
+            match bet_info.direction {
                 Direction::Bull => {
                     live_bull_amount = bet_info.amount;
                 }
                 Direction::Bear => {
                     live_bear_amount = bet_info.amount;
                 }
-            },
-            None => {}
+            }
         }
     }
 
@@ -113,9 +193,14 @@ fn query_status(deps: Deps, env: Env) -> StdResult<StatusResponse> {
     let total_volume = TOTAL_VOLUME.load(deps.storage)?;
     let current_time = env.block.time.seconds();
 
-    let finished_round_id = live_round.clone().unwrap().id - Uint128::new(1);
-
-    let finished_round = ROUNDS.load(deps.storage, finished_round_id.u128())?;
+    /* The last finished round sits right behind the live one, or behind the bidding one */
+    let finished_round_id = match &live_round {
+        Some(live_round) => live_round.id.u128().checked_sub(1),
+        None => NEXT_ROUND_ID.load(deps.storage)?.checked_sub(2),
+    };
+    /* Before any round finished this is the empty placeholder round */
+    let finished_round =
+        query_finished_round(deps, Uint128::new(finished_round_id.unwrap_or_default()))?;
 
     Ok(StatusResponse {
         bidding_round,
@@ -183,57 +268,197 @@ pub fn query_reverse_my_games(
 }
 
 pub fn query_my_pending_reward(deps: Deps, player: Addr) -> StdResult<PendingRewardResponse> {
-    let my_game_list = query_my_games_without_limit(deps, player.clone())?;
-    let mut winnings = Uint128::zero();
+    let pending_reward = settle_player(deps, &player)?
+        .iter()
+        .map(|claimable_round| claimable_round.payout)
+        .sum();
 
-    for game in my_game_list.my_game_list {
-        let round_id = game.round_id;
-        let round = ROUNDS.may_load(deps.storage, round_id.u128())?;
+    Ok(PendingRewardResponse { pending_reward })
+}
+
+fn query_claimable_rounds(deps: Deps, player: Addr) -> StdResult<ClaimableRoundsResponse> {
+    let claimable_rounds = settle_player(deps, &player)?;
+    let pending_reward = claimable_rounds
+        .iter()
+        .map(|claimable_round| claimable_round.payout)
+        .sum();
+
+    Ok(ClaimableRoundsResponse {
+        claimable_rounds,
+        pending_reward,
+    })
+}
+
+fn query_expiring_rounds(
+    deps: Deps,
+    env: Env,
+    player: Addr,
+    within_seconds: u64,
+) -> StdResult<ExpiringRoundsResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    let horizon = env.block.time.plus_seconds(within_seconds);
+    let mut expiring_rounds = vec![];
 
-        if round.is_none() {
+    for claimable_round in settle_player(deps, &player)? {
+        if claimable_round.payout.is_zero() {
             continue;
         }
-        let round = round.unwrap();
-
-        let pool_shares = round.bear_amount + round.bull_amount;
-
-        if round.bear_amount == Uint128::zero() || round.bull_amount == Uint128::zero() {
-            winnings += game.amount;
-        } else {
-            let round_winnings = match round.winner {
-                Some(Direction::Bull) => {
-                    /* Only claimable once */
-                    match game.direction {
-                        Direction::Bull => {
-                            let won_shares = game.amount;
-                            pool_shares.multiply_ratio(won_shares, round.bull_amount)
-                        }
-                        Direction::Bear => Uint128::zero(),
-                    }
-                }
-                Some(Direction::Bear) => {
-                    /* Only claimable once */
-                    match game.direction {
-                        Direction::Bull => Uint128::zero(),
-                        Direction::Bear => {
-                            let won_shares = game.amount;
-                            pool_shares.multiply_ratio(won_shares, round.bear_amount)
-                        }
-                    }
-                }
-                None => {
-                    /* Only claimable once */
-                    game.amount
-                }
-            };
+        let round = ROUNDS.load(deps.storage, claimable_round.round_id.u128())?;
+        if let Some(expires_at) = round_expires_at(&config, &round) {
+            if expires_at <= horizon {
+                expiring_rounds.push(ExpiringRound {
+                    round_id: claimable_round.round_id,
+                    payout: claimable_round.payout,
+                    expires_at,
+                });
+            }
+        }
+    }
+
+    Ok(ExpiringRoundsResponse { expiring_rounds })
+}
+
+fn query_balance(deps: Deps, player: Addr) -> StdResult<PlayerBalanceResponse> {
+    let balance = BALANCES
+        .may_load(deps.storage, &player)?
+        .unwrap_or_default();
+    Ok(PlayerBalanceResponse { balance })
+}
+
+fn query_strategy(deps: Deps, player: Addr) -> StdResult<StrategyResponse> {
+    let strategy = STRATEGIES.may_load(deps.storage, &player)?;
+    Ok(StrategyResponse { strategy })
+}
+
+fn query_operators(deps: Deps, player: Addr) -> StdResult<OperatorsResponse> {
+    let operators = OPERATORS
+        .prefix(&player)
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| {
+            item.map(|(operator, approval)| Operator {
+                operator,
+                spend_limit: approval.spend_limit,
+                expires: approval.expires,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(OperatorsResponse { operators })
+}
+
+fn query_referral(deps: Deps, address: Addr) -> StdResult<ReferralResponse> {
+    let (earned, pending, _) = resolve_referrals(deps.storage, &address)?;
+    Ok(ReferralResponse {
+        referrer: REFERRERS.may_load(deps.storage, &address)?,
+        earnings: REFERRAL_EARNINGS
+            .may_load(deps.storage, &address)?
+            .unwrap_or_default()
+            + earned,
+        pending,
+        referred_count: REFERRAL_COUNTS
+            .may_load(deps.storage, &address)?
+            .unwrap_or_default(),
+    })
+}
+
+fn query_pending_settlements(deps: Deps) -> StdResult<PendingSettlementsResponse> {
+    let pending_settlements = PENDING_SETTLEMENTS
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| {
+            item.map(|(round_id, cursor)| PendingSettlement {
+                round_id: Uint128::new(round_id),
+                last_player: cursor.last_player,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(PendingSettlementsResponse {
+        pending_settlements,
+    })
+}
+
+fn query_solvency(deps: Deps, env: Env) -> StdResult<SolvencyResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    let token_balance: BalanceResponse = deps.querier.query_wasm_smart(
+        config.token_addr,
+        &Cw20QueryMsg::Balance {
+            address: env.contract.address.to_string(),
+        },
+    )?;
 
-            /* Count it up */
-            winnings += round_winnings;
+    /* Bets still stored in finished rounds are owed their payout */
+    let mut unclaimed_winnings = Uint128::zero();
+    let mut unsettled_rounds: Vec<(FinishedRound, Uint128)> = vec![];
+    for item in bet_info_storage().range(deps.storage, None, None, Order::Ascending) {
+        let (_, bet) = item?;
+        if let Some(round) = ROUNDS.may_load(deps.storage, bet.round_id.u128())? {
+            let payout = compute_bet_payout(&round, &bet);
+            unclaimed_winnings += payout;
+            /* Bets are stored by round, so a round's bets come one after another */
+            match unsettled_rounds.last_mut() {
+                Some((last, winnings)) if last.id == round.id => *winnings += payout,
+                _ => unsettled_rounds.push((round, payout)),
+            }
         }
     }
 
-    Ok(PendingRewardResponse {
-        pending_reward: winnings,
+    /* Stakes in the bidding and live round go back to the players one way or another */
+    let mut open_stakes = Uint128::zero();
+    if let Some(next_round) = NEXT_ROUND.may_load(deps.storage)? {
+        open_stakes += next_round.bull_amount + next_round.bear_amount;
+    }
+    if let Some(live_round) = LIVE_ROUND.may_load(deps.storage)? {
+        open_stakes += live_round.bull_amount + live_round.bear_amount;
+    }
+
+    let accumulated_fee = Uint128::from(ACCUMULATED_FEE.load(deps.storage)?);
+    /* Dust of rounds with winners still to settle is in the pool, just not booked yet */
+    let dust = DUST.load(deps.storage)?
+        + unsettled_rounds
+            .iter()
+            .map(|(round, winnings)| unbooked_dust(deps.storage, round, *winnings))
+            .sum::<StdResult<Uint128>>()?;
+    let rollover_pool = ROLLOVER_POOL.load(deps.storage)?;
+    let player_balances = BALANCES
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, balance)| balance))
+        .sum::<StdResult<Uint128>>()?;
+    let strategy_budgets = STRATEGIES
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, strategy)| strategy.budget))
+        .sum::<StdResult<Uint128>>()?;
+    /* Shares held for refunded rounds are already in the refunds */
+    let mut referral_earnings = REFERRAL_EARNINGS
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, earnings)| earnings))
+        .sum::<StdResult<Uint128>>()?;
+    for item in PENDING_REFERRALS.range(deps.storage, None, None, Order::Ascending) {
+        let ((_, round_id), amount) = item?;
+        match ROUNDS.may_load(deps.storage, round_id)? {
+            Some(round) if round.tie_policy == Some(TiePolicy::Refund) => {}
+            _ => referral_earnings += amount,
+        }
+    }
+
+    let liabilities = unclaimed_winnings
+        + open_stakes
+        + accumulated_fee
+        + dust
+        + rollover_pool
+        + player_balances
+        + strategy_budgets
+        + referral_earnings;
+
+    Ok(SolvencyResponse {
+        token_balance: token_balance.balance,
+        unclaimed_winnings,
+        open_stakes,
+        accumulated_fee,
+        dust,
+        rollover_pool,
+        player_balances,
+        strategy_budgets,
+        referral_earnings,
+        liabilities,
+        is_solvent: token_balance.balance >= liabilities,
     })
 }
 
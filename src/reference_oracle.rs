@@ -0,0 +1,198 @@
+/**
+ * Reference implementation of the fast oracle the game reads prices from.
+ * The owner manages who may feed prices, every fed price is kept in the history.
+ * Build with `--features reference-oracle` to get this contract instead of the game.
+ */
+use cosmwasm_std::{
+    to_binary, Addr, Binary, Deps, DepsMut, Empty, Env, MessageInfo, Order, Response, StdError,
+    StdResult, Uint128,
+};
+use cw_storage_plus::{Bound, Item, Map};
+
+use crate::error::ContractError;
+use crate::msg::{
+    FastOracleConfigResponse, FastOracleExecuteMsg, FastOracleHistoryResponse,
+    FastOracleInstantiateMsg, FastOraclePriceData, FastOracleQueryMsg,
+};
+
+#[cfg(all(feature = "reference-oracle", not(feature = "library")))]
+use cosmwasm_std::entry_point;
+
+const CONTRACT_NAME: &str = "reference_fast_oracle";
+const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// Query limits
+const DEFAULT_QUERY_LIMIT: u32 = 10;
+const MAX_QUERY_LIMIT: u32 = 30;
+
+pub const OWNER: Item<Addr> = Item::new("oracle_owner");
+pub const FEEDERS: Map<&Addr, Empty> = Map::new("oracle_feeders");
+/* Every fed price by its id, the latest has the highest */
+pub const PRICES: Map<u64, FastOraclePriceData> = Map::new("oracle_prices");
+pub const NEXT_PRICE_ID: Item<u64> = Item::new("oracle_next_price_id");
+
+#[cfg_attr(
+    all(feature = "reference-oracle", not(feature = "library")),
+    entry_point
+)]
+pub fn instantiate(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: FastOracleInstantiateMsg,
+) -> Result<Response, ContractError> {
+    cw2::set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    let owner = match msg.owner {
+        Some(owner) => deps.api.addr_validate(owner.as_str())?,
+        None => info.sender.clone(),
+    };
+    OWNER.save(deps.storage, &owner)?;
+    for feeder in msg.feeders {
+        let feeder = deps.api.addr_validate(feeder.as_str())?;
+        FEEDERS.save(deps.storage, &feeder, &Empty {})?;
+    }
+    NEXT_PRICE_ID.save(deps.storage, &0u64)?;
+    if let Some(price) = msg.price {
+        feed_price(deps, &env, info.sender, price)?;
+    }
+
+    Ok(Response::new().add_attribute("owner", owner))
+}
+
+#[cfg_attr(
+    all(feature = "reference-oracle", not(feature = "library")),
+    entry_point
+)]
+pub fn execute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: FastOracleExecuteMsg,
+) -> Result<Response, ContractError> {
+    match msg {
+        FastOracleExecuteMsg::Update { price } => execute_update(deps, env, info, price),
+        FastOracleExecuteMsg::Owner { owner } => execute_owner(deps, info, owner),
+        FastOracleExecuteMsg::AddFeeder { feeder } => {
+            assert_is_owner(deps.as_ref(), &info)?;
+            let feeder = deps.api.addr_validate(feeder.as_str())?;
+            FEEDERS.save(deps.storage, &feeder, &Empty {})?;
+            Ok(Response::new()
+                .add_attribute("action", "add-feeder")
+                .add_attribute("feeder", feeder))
+        }
+        FastOracleExecuteMsg::RemoveFeeder { feeder } => {
+            assert_is_owner(deps.as_ref(), &info)?;
+            FEEDERS.remove(deps.storage, &feeder);
+            Ok(Response::new()
+                .add_attribute("action", "remove-feeder")
+                .add_attribute("feeder", feeder))
+        }
+    }
+}
+
+fn execute_update(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    price: Uint128,
+) -> Result<Response, ContractError> {
+    /* The owner can always feed, e.g. while no feeders are set up */
+    if info.sender != OWNER.load(deps.storage)? && !FEEDERS.has(deps.storage, &info.sender) {
+        return Err(ContractError::Unauthorized {});
+    }
+    let data = feed_price(deps, &env, info.sender, price)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "update")
+        .add_attribute("price", data.price)
+        .add_attribute("price_id", data.id.to_string()))
+}
+
+fn execute_owner(deps: DepsMut, info: MessageInfo, owner: Addr) -> Result<Response, ContractError> {
+    assert_is_owner(deps.as_ref(), &info)?;
+    let owner = deps.api.addr_validate(owner.as_str())?;
+    OWNER.save(deps.storage, &owner)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "owner")
+        .add_attribute("owner", owner))
+}
+
+fn feed_price(
+    deps: DepsMut,
+    env: &Env,
+    feeder: Addr,
+    price: Uint128,
+) -> StdResult<FastOraclePriceData> {
+    let id = NEXT_PRICE_ID.load(deps.storage)?;
+    let data = FastOraclePriceData {
+        id,
+        price,
+        feeder,
+        updated_at: env.block.time,
+        height: env.block.height,
+    };
+    PRICES.save(deps.storage, id, &data)?;
+    NEXT_PRICE_ID.save(deps.storage, &(id + 1))?;
+    Ok(data)
+}
+
+fn assert_is_owner(deps: Deps, info: &MessageInfo) -> Result<(), ContractError> {
+    if info.sender != OWNER.load(deps.storage)? {
+        return Err(ContractError::Unauthorized {});
+    }
+    Ok(())
+}
+
+#[cfg_attr(
+    all(feature = "reference-oracle", not(feature = "library")),
+    entry_point
+)]
+pub fn query(deps: Deps, _env: Env, msg: FastOracleQueryMsg) -> StdResult<Binary> {
+    match msg {
+        FastOracleQueryMsg::Price {} => to_binary(&query_price_data(deps)?.price),
+        FastOracleQueryMsg::PriceData {} => to_binary(&query_price_data(deps)?),
+        FastOracleQueryMsg::History { start_after, limit } => {
+            to_binary(&query_history(deps, start_after, limit)?)
+        }
+        FastOracleQueryMsg::Config {} => to_binary(&query_config(deps)?),
+    }
+}
+
+fn query_price_data(deps: Deps) -> StdResult<FastOraclePriceData> {
+    PRICES
+        .range(deps.storage, None, None, Order::Descending)
+        .next()
+        .transpose()?
+        .map(|(_, data)| data)
+        .ok_or_else(|| StdError::generic_err("No price has been fed yet"))
+}
+
+fn query_history(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<FastOracleHistoryResponse> {
+    let limit = limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT) as usize;
+    let end = start_after.map(Bound::exclusive);
+
+    let prices = PRICES
+        .range(deps.storage, None, end, Order::Descending)
+        .take(limit)
+        .map(|item| item.map(|(_, data)| data))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(FastOracleHistoryResponse { prices })
+}
+
+fn query_config(deps: Deps) -> StdResult<FastOracleConfigResponse> {
+    let feeders = FEEDERS
+        .keys(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(FastOracleConfigResponse {
+        owner: OWNER.load(deps.storage)?,
+        feeders,
+    })
+}
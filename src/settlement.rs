@@ -0,0 +1,164 @@
+use cosmwasm_std::{Addr, Deps, Order, StdResult, Storage, Timestamp, Uint128};
+
+use crate::msg::{ClaimableRound, Config, Direction, FinishedRound, TiePolicy};
+use crate::query::query_my_games_without_limit;
+use crate::state::{BetInfo, DUST, PENDING_REFERRALS, ROUNDS, UNTRACKED_ROUNDS};
+
+/**
+ * Parimutuel payout of a single bet in a finished round
+ */
+pub fn compute_bet_payout(round: &FinishedRound, bet: &BetInfo) -> Uint128 {
+    let pool_shares = payout_pool(round);
+
+    match (&round.tie_policy, &round.winner) {
+        (Some(TiePolicy::Refund), _) => bet.amount + bet.fee + bet.referral_fee,
+        /* Rounds finished before the migration refunded the stake when nobody could win */
+        (None, _)
+            if round.winner.is_none()
+                || round.bull_amount.is_zero()
+                || round.bear_amount.is_zero() =>
+        {
+            bet.amount
+        }
+        (None, Some(winner)) if *winner != bet.direction => Uint128::zero(),
+        (None, Some(Direction::Bull)) => pool_shares.multiply_ratio(bet.amount, round.bull_amount),
+        (None, Some(Direction::Bear)) => pool_shares.multiply_ratio(bet.amount, round.bear_amount),
+        /* The house or the next round took the pool */
+        (Some(_), _) | (None, None) => Uint128::zero(),
+    }
+}
+
+/**
+ * What the winners of a decided round share
+ */
+pub fn payout_pool(round: &FinishedRound) -> Uint128 {
+    round.bear_amount + round.bull_amount + round.rollover_amount - round.rake
+}
+
+/**
+ * Every bet of the player in a finished round, with what it pays out.
+ * Bets in the bidding or live round are not settled yet and left out.
+ */
+pub fn settle_player(deps: Deps, player: &Addr) -> StdResult<Vec<ClaimableRound>> {
+    let my_game_list = query_my_games_without_limit(deps, player.clone())?;
+    let mut claimable_rounds = vec![];
+
+    for game in my_game_list.my_game_list {
+        let round = match ROUNDS.may_load(deps.storage, game.round_id.u128())? {
+            Some(round) => round,
+            None => continue,
+        };
+
+        claimable_rounds.push(ClaimableRound {
+            round_id: game.round_id,
+            direction: game.direction.clone(),
+            amount: game.amount,
+            payout: compute_bet_payout(&round, &game),
+        });
+    }
+
+    Ok(claimable_rounds)
+}
+
+/**
+ * A referrer's shares held with rounds, as (earned, still pending, rounds resolved).
+ * Decided rounds earn the share, refunded rounds gave it back to the bettors.
+ */
+pub fn resolve_referrals(
+    storage: &dyn Storage,
+    referrer: &Addr,
+) -> StdResult<(Uint128, Uint128, Vec<u128>)> {
+    let mut earned = Uint128::zero();
+    let mut pending = Uint128::zero();
+    let mut resolved = vec![];
+
+    for item in PENDING_REFERRALS
+        .prefix(referrer)
+        .range(storage, None, None, Order::Ascending)
+    {
+        let (round_id, amount) = item?;
+        match ROUNDS.may_load(storage, round_id)? {
+            None => pending += amount,
+            Some(round) => {
+                if round.tie_policy != Some(TiePolicy::Refund) {
+                    earned += amount;
+                }
+                resolved.push(round_id);
+            }
+        }
+    }
+
+    Ok((earned, pending, resolved))
+}
+
+/**
+ * Stake on the side that won the round, none when the round has no winner
+ * or its payouts aren't tracked
+ */
+fn winning_stake(storage: &dyn Storage, round: &FinishedRound) -> StdResult<Option<Uint128>> {
+    if let Some(untracked_rounds) = UNTRACKED_ROUNDS.may_load(storage)? {
+        if round.id.u128() < untracked_rounds {
+            return Ok(None);
+        }
+    }
+
+    Ok(match (&round.tie_policy, &round.winner) {
+        (None, Some(Direction::Bull)) => Some(round.bull_amount),
+        (None, Some(Direction::Bear)) => Some(round.bear_amount),
+        _ => None,
+    })
+}
+
+/**
+ * Books a settled bet's payout with its round, the caller saves the round.
+ * Payouts round down, what is left of the pool once the last winner is settled is dust.
+ */
+pub fn record_payout(
+    storage: &mut dyn Storage,
+    round: &mut FinishedRound,
+    direction: &Direction,
+    amount: Uint128,
+    payout: Uint128,
+) -> StdResult<()> {
+    let winning_stake = match winning_stake(storage, round)? {
+        Some(winning_stake) => winning_stake,
+        None => return Ok(()),
+    };
+    if round.winner.as_ref() != Some(direction) {
+        return Ok(());
+    }
+
+    round.paid_out += payout;
+    round.settled_stake += amount;
+    if round.settled_stake == winning_stake {
+        round.dust = payout_pool(round) - round.paid_out;
+        let round_dust = round.dust;
+        DUST.update(storage, |dust| -> StdResult<_> { Ok(dust + round_dust) })?;
+    }
+    Ok(())
+}
+
+/**
+ * Dust the round will book once its last winner is settled, given what its bets still pay out
+ */
+pub fn unbooked_dust(
+    storage: &dyn Storage,
+    round: &FinishedRound,
+    unclaimed_winnings: Uint128,
+) -> StdResult<Uint128> {
+    Ok(match winning_stake(storage, round)? {
+        Some(winning_stake) if round.settled_stake < winning_stake => {
+            payout_pool(round) - round.paid_out - unclaimed_winnings
+        }
+        _ => Uint128::zero(),
+    })
+}
+
+/**
+ * When unclaimed winnings of the round can be expired, if the config has a claim window
+ */
+pub fn round_expires_at(config: &Config, round: &FinishedRound) -> Option<Timestamp> {
+    config
+        .claim_window_seconds
+        .map(|window| round.close_time.plus_seconds(window.u128() as u64))
+}
@@ -1,8 +1,8 @@
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::{Addr, Uint128};
+use cosmwasm_std::{Addr, Empty, StdResult, Storage, Timestamp, Uint128};
 use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex};
 
-use crate::msg::{Config, Direction, FinishedRound, LiveRound, NextRound};
+use crate::msg::{Config, Direction, FinishedRound, LiveRound, NextRound, StrategyKind};
 
 pub const IS_HAULTED: Item<bool> = Item::new("is_haulted");
 pub const CONFIG: Item<Config> = Item::new("config");
@@ -14,16 +14,131 @@ pub const LIVE_ROUND: Item<LiveRound> = Item::new("live_round");
 
 pub const ACCUMULATED_FEE: Item<u128> = Item::new("accumulated_fee");
 
+/* Pool of void rounds waiting for the next round with a winner */
+pub const ROLLOVER_POOL: Item<Uint128> = Item::new("rollover_pool");
+
+/* Rounding leftovers of settled rounds, swept by the treasury */
+pub const DUST: Item<Uint128> = Item::new("dust");
+
 pub const ROUNDS: Map<u128, FinishedRound> = Map::new("rounds");
 
 pub const TOTAL_VOLUME: Item<Uint128> = Item::new("total_volume");
 
+/* The config as the first version stored it, read once when migrating */
+#[cw_serde]
+pub struct LegacyConfig {
+    pub next_round_seconds: Uint128,
+    pub fast_oracle_addr: Addr,
+    pub minimum_bet: Uint128,
+    pub burn_fee: Uint128,
+    pub gaming_fee: Uint128,
+    pub token_addr: Addr,
+}
+
+pub const LEGACY_CONFIG: Item<LegacyConfig> = Item::new("config");
+
+/* Rounds below this id finished before the migration from the first version, their payouts
+ * weren't booked with the round */
+pub const UNTRACKED_ROUNDS: Item<u128> = Item::new("untracked_rounds");
+
+/* Where the winnings push of a finished round left off */
+#[cw_serde]
+pub struct SettlementCursor {
+    pub last_player: Option<Addr>,
+}
+
+/* Finished rounds whose winnings are still being pushed out, by round id */
+pub const PENDING_SETTLEMENTS: Map<u128, SettlementCursor> = Map::new("pending_settlements");
+
+/* Balance-funded bets that are not settled yet, by round and player */
+pub const BALANCE_BETS: Map<(u128, &Addr), Empty> = Map::new("balance_bets");
+
+/* Tokens players keep inside the contract to bet from */
+pub const BALANCES: Map<&Addr, Uint128> = Map::new("balances");
+
+#[cw_serde]
+pub struct Strategy {
+    pub kind: StrategyKind,
+    /* Gross amount of every bet */
+    pub amount: Uint128,
+    pub rounds_remaining: u32,
+    /* Pre-funded tokens the bets are paid from */
+    pub budget: Uint128,
+}
+
+/* Recurring bets placed for players whenever a round opens for betting */
+pub const STRATEGIES: Map<&Addr, Strategy> = Map::new("strategies");
+
+/* Where placing strategy bets on the round open for betting left off */
+#[cw_serde]
+pub struct StrategyCursor {
+    pub round_id: Uint128,
+    pub last_player: Option<Addr>,
+}
+
+pub const STRATEGY_CURSOR: Item<StrategyCursor> = Item::new("strategy_cursor");
+
+#[cw_serde]
+pub struct OperatorApproval {
+    /* What the operator can still bet for the player */
+    pub spend_limit: Uint128,
+    pub expires: Option<Timestamp>,
+}
+
+/* Hot keys allowed to bet for a player, by (player, operator) */
+pub const OPERATORS: Map<(&Addr, &Addr), OperatorApproval> = Map::new("operators");
+
+/* Who referred each bettor, set once */
+pub const REFERRERS: Map<&Addr, Addr> = Map::new("referrers");
+/* Fee share owed to referrers, claimed separately */
+pub const REFERRAL_EARNINGS: Map<&Addr, Uint128> = Map::new("referral_earnings");
+/* Fee share held with a round until it is decided, by (referrer, round id) */
+pub const PENDING_REFERRALS: Map<(&Addr, u128), Uint128> = Map::new("pending_referrals");
+/* Number of bettors each referrer brought in */
+pub const REFERRAL_COUNTS: Map<&Addr, u32> = Map::new("referral_counts");
+
+#[cw_serde]
+pub struct PlayerVolume {
+    /* Start of the current volume window */
+    pub window_start: Timestamp,
+    pub current: Uint128,
+    pub previous: Uint128,
+}
+
+/* Gross amount each player bet recently, for fee tiers */
+pub const PLAYER_VOLUMES: Map<&Addr, PlayerVolume> = Map::new("player_volumes");
+
+#[cw_serde]
+pub struct TwapObservation {
+    /* Spot price at the sample */
+    pub price: Uint128,
+    /* Time-weighted sum of the price since the first sample, in price-seconds */
+    pub cumulative: Uint128,
+}
+
+/* Samples of each wasmswap pool's spot price, by pool and the second they were taken */
+pub const TWAP_OBSERVATIONS: Map<(&Addr, u64), TwapObservation> = Map::new("twap_observations");
+
+/* Bets placed before the migration have no fee split, they were charged the old way */
 #[cw_serde]
 pub struct BetInfo {
     pub player: Addr,
     pub round_id: Uint128,
+    /* Net of the fee, this is what is in the pool */
     pub amount: Uint128,
+    /* What the player paid in */
+    #[serde(default)]
+    pub gross_amount: Uint128,
+    /* The part of the fee that went to the accumulated fee */
+    #[serde(default)]
+    pub fee: Uint128,
+    /* The part of the fee held for the referrer until the round is decided */
+    #[serde(default)]
+    pub referral_fee: Uint128,
     pub direction: Direction,
+    /* Funded from the player's balance, winnings are credited back there at close */
+    #[serde(default)]
+    pub from_balance: bool,
 }
 
 /// Primary key for betinfo: (round_id, player)
@@ -33,6 +148,13 @@ pub fn bet_info_key(round_id: u128, player: &Addr) -> BetInfoKey {
     (round_id, player.clone())
 }
 
+/* Settled or cancelled bets are removed together with their balance-funded marker */
+pub fn remove_bet(storage: &mut dyn Storage, round_id: u128, player: &Addr) -> StdResult<()> {
+    bet_info_storage().remove(storage, bet_info_key(round_id, player))?;
+    BALANCE_BETS.remove(storage, (round_id, player));
+    Ok(())
+}
+
 /// Defines incides for accessing bids
 pub struct BetInfoIndicies<'a> {
     pub player: MultiIndex<'a, Addr, BetInfo, BetInfoKey>,
@@ -65,3 +187,24 @@ pub struct MyGameResponse {
 pub struct PendingRewardResponse {
     pub pending_reward: Uint128,
 }
+
+#[cw_serde]
+pub struct PlayerBalanceResponse {
+    pub balance: Uint128,
+}
+
+#[cw_serde]
+pub struct StrategyResponse {
+    pub strategy: Option<Strategy>,
+}
+
+#[cw_serde]
+pub struct ReferralResponse {
+    /* Who referred this address */
+    pub referrer: Option<Addr>,
+    /* What this address earned as a referrer and has not claimed */
+    pub earnings: Uint128,
+    /* Held for rounds that are not decided yet, refunded rounds give it back to the bettor */
+    pub pending: Uint128,
+    pub referred_count: u32,
+}
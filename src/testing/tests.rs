@@ -1,10 +1,11 @@
-use cosmwasm_std::testing::mock_env;
+use cosmwasm_schema::cw_serde;
 use cosmwasm_std::{
-    coins, to_binary, Addr, Binary, BlockInfo, Coin, CosmosMsg, Decimal, Empty, Response,
-    StdResult, Timestamp, Uint128, WasmMsg,
+    from_binary, to_binary, Addr, Binary, BlockInfo, CosmosMsg, Decimal, Empty, Response, StdError,
+    StdResult, Timestamp, Uint128, Uint64, WasmMsg,
 };
-use cw20::{BalanceResponse, Cw20Coin, Cw20ExecuteMsg, Cw20QueryMsg};
+use cw20::{BalanceResponse, Cw20Coin, Cw20ExecuteMsg, Cw20QueryMsg, Cw20ReceiveMsg, Denom};
 use cw_multi_test::{App, Contract, ContractWrapper, Executor};
+use cw_storage_plus::{Item, Map};
 
 use cw20_base::msg::InstantiateMsg as Cw20InstantiateMsg;
 
@@ -12,11 +13,25 @@ use cw20_base::msg::InstantiateMsg as Cw20InstantiateMsg;
 use std::convert::TryInto;
 // use std::ops::Add;
 
+use crate::error::ContractError;
 use crate::msg::{
-    Config, ConfigResponse, Direction, ExecuteMsg, FastOracleExecuteMsg, FastOracleInstantiateMsg,
-    FastOracleQueryMsg, InstantiateMsg, QueryMsg, StatusResponse,
+    ClaimableRoundsResponse, Config, ConfigResponse, Direction, ExecuteMsg, ExpiringRoundsResponse,
+    ExpiryDestination, FastOracleConfigResponse, FastOracleExecuteMsg, FastOracleHistoryResponse,
+    FastOracleInstantiateMsg, FastOraclePriceData, FastOracleQueryMsg, FeeDistribution, FeeMode,
+    FeeTier, FeeTierResponse, InstantiateMsg, MigrateMsg, MyCurrentPositionResponse,
+    OperatorsResponse, OracleHealthResponse, OracleSource, PendingSettlementsResponse,
+    PriceObservation, PriceSource, PushSettlement, QueryMsg, RoundResponse, SolvencyResponse,
+    StakeQueryMsg, StakedBalanceAtHeightResponse, StakingRewardsReceiveMsg, StatusResponse,
+    StrategyKind, TiePolicy,
+};
+use crate::oracle::{
+    BandQueryMsg, BandReferenceData, PythPrice, PythPriceFeed, PythPriceFeedResponse, PythQueryMsg,
+    WasmswapInfoResponse, WasmswapQueryMsg,
+};
+use crate::state::{
+    bet_info_key, bet_info_storage, BetInfo, LegacyConfig, MyGameResponse, PendingRewardResponse,
+    PlayerBalanceResponse, ReferralResponse, StrategyResponse, LEGACY_CONFIG,
 };
-use crate::state::{MyGameResponse, PendingRewardResponse};
 
 fn mock_app() -> App {
     App::default()
@@ -27,36 +42,177 @@ pub fn contract_price_prediction() -> Box<dyn Contract<Empty>> {
         crate::execute::execute,
         crate::execute::instantiate,
         crate::query::query,
-    );
+    )
+    .with_migrate(crate::execute::migrate);
     Box::new(contract)
 }
 
 pub fn contract_fast_oracle() -> Box<dyn Contract<Empty>> {
     let contract = ContractWrapper::new(
-        |deps, _, _info, msg: FastOracleExecuteMsg| -> StdResult<Response> {
+        crate::reference_oracle::execute,
+        crate::reference_oracle::instantiate,
+        crate::reference_oracle::query,
+    );
+    Box::new(contract)
+}
+
+#[cw_serde]
+pub enum StakingRewardsExecuteMsg {
+    Receive(Cw20ReceiveMsg),
+}
+
+/* Stands in for stake_cw20_external_rewards, only accepts being funded */
+pub fn contract_staking_rewards() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(
+        |_, _, _, msg: StakingRewardsExecuteMsg| -> StdResult<Response> {
+            match msg {
+                StakingRewardsExecuteMsg::Receive(receive_msg) => {
+                    match from_binary::<StakingRewardsReceiveMsg>(&receive_msg.msg)? {
+                        StakingRewardsReceiveMsg::Fund {} => Ok(Response::default()),
+                    }
+                }
+            }
+        },
+        |_, _, _, _: Empty| -> StdResult<Response> { Ok(Response::default()) },
+        |_, _, _: Empty| -> StdResult<Binary> { to_binary(&Empty {}) },
+    );
+    Box::new(contract)
+}
+
+/* Stands in for stake_cw20, user2 has 500 staked */
+pub fn contract_staking() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(
+        |_, _, _, _: Empty| -> StdResult<Response> { Ok(Response::default()) },
+        |_, _, _, _: Empty| -> StdResult<Response> { Ok(Response::default()) },
+        |_, env, msg: StakeQueryMsg| -> StdResult<Binary> {
             match msg {
-                FastOracleExecuteMsg::Update { price } => {
-                    deps.storage.set(b"price", &price.to_be_bytes());
-                    Ok(Response::default())
+                StakeQueryMsg::StakedBalanceAtHeight { address, .. } => {
+                    to_binary(&StakedBalanceAtHeightResponse {
+                        balance: match address.as_str() {
+                            "user2" => Uint128::new(500),
+                            _ => Uint128::zero(),
+                        },
+                        height: env.block.height,
+                    })
                 }
-                FastOracleExecuteMsg::Owner { owner: _ } => todo!(),
             }
         },
-        |deps, _, _, _: FastOracleInstantiateMsg| -> StdResult<Response> {
+    );
+    Box::new(contract)
+}
+
+/* A fast oracle without the feed history, it only answers Price */
+pub fn contract_bare_fast_oracle() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(
+        |_, _, _, _: Empty| -> StdResult<Response> { Ok(Response::default()) },
+        |_, _, _, _: Empty| -> StdResult<Response> { Ok(Response::default()) },
+        |_, _, msg: FastOracleQueryMsg| -> StdResult<Binary> {
+            match msg {
+                FastOracleQueryMsg::Price {} => to_binary(&Uint128::new(1_000_000)),
+                _ => Err(StdError::generic_err("Unsupported query")),
+            }
+        },
+    );
+    Box::new(contract)
+}
+
+/* Pyth-style feed, prices are updated like the fast oracle's and have 8 decimals */
+pub fn contract_pyth_oracle() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(
+        |deps, _, _, msg: FastOracleExecuteMsg| -> StdResult<Response> {
+            if let FastOracleExecuteMsg::Update { price } = msg {
+                deps.storage.set(b"price", &price.to_be_bytes());
+            }
+            Ok(Response::default())
+        },
+        |deps, _, _, _: Empty| -> StdResult<Response> {
             deps.storage
-                .set(b"price", &Uint128::new(1_000_000u128).to_be_bytes());
+                .set(b"price", &Uint128::new(200_000_000u128).to_be_bytes());
             Ok(Response::default())
         },
-        |deps, _, msg: FastOracleQueryMsg| -> StdResult<Binary> {
-            match msg {
-                FastOracleQueryMsg::Price {} => {
-                    let res = deps.storage.get(b"price").unwrap_or_default();
-                    let price =
-                        Uint128::from(u128::from_be_bytes(res.as_slice().try_into().unwrap()));
+        |deps, env, msg: PythQueryMsg| -> StdResult<Binary> {
+            let PythQueryMsg::PriceFeed { id } = msg;
+            let res = deps.storage.get(b"price").unwrap_or_default();
+            let price = PythPrice {
+                price: u128::from_be_bytes(res.as_slice().try_into().unwrap()).to_string(),
+                conf: Uint64::new(50_000),
+                expo: -8,
+                publish_time: env.block.time.seconds() as i64,
+            };
+            to_binary(&PythPriceFeedResponse {
+                price_feed: PythPriceFeed {
+                    id,
+                    price: price.clone(),
+                    ema_price: price,
+                },
+            })
+        },
+    );
+    Box::new(contract)
+}
 
-                    to_binary(&price)
-                }
+/* Band-style reference data, rates are updated like the fast oracle's and have 18 decimals */
+pub fn contract_band_oracle() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(
+        |deps, _, _, msg: FastOracleExecuteMsg| -> StdResult<Response> {
+            if let FastOracleExecuteMsg::Update { price } = msg {
+                deps.storage.set(b"price", &price.to_be_bytes());
+            }
+            Ok(Response::default())
+        },
+        |deps, _, _, _: Empty| -> StdResult<Response> {
+            deps.storage.set(
+                b"price",
+                &Uint128::new(2_000_000_000_000_000_000u128).to_be_bytes(),
+            );
+            Ok(Response::default())
+        },
+        |deps, env, _: BandQueryMsg| -> StdResult<Binary> {
+            let res = deps.storage.get(b"price").unwrap_or_default();
+            to_binary(&BandReferenceData {
+                rate: Uint128::from(u128::from_be_bytes(res.as_slice().try_into().unwrap())).into(),
+                last_updated_base: env.block.time.seconds(),
+                last_updated_quote: env.block.time.seconds() - 10,
+            })
+        },
+    );
+    Box::new(contract)
+}
+
+/* Wasmswap pool with 1 token1 against the updated price worth of token2 */
+pub fn contract_wasmswap_pool() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(
+        |deps, _, _, msg: FastOracleExecuteMsg| -> StdResult<Response> {
+            if let FastOracleExecuteMsg::Update { price } = msg {
+                deps.storage.set(b"price", &price.to_be_bytes());
             }
+            Ok(Response::default())
+        },
+        |deps, _, _, _: Empty| -> StdResult<Response> {
+            deps.storage
+                .set(b"price", &Uint128::new(1_000_000u128).to_be_bytes());
+            Ok(Response::default())
+        },
+        |deps, _, _: WasmswapQueryMsg| -> StdResult<Binary> {
+            let res = deps.storage.get(b"price").unwrap_or_default();
+            let price = Uint128::from(u128::from_be_bytes(res.as_slice().try_into().unwrap()));
+            /* A zero price drains the pool */
+            to_binary(&WasmswapInfoResponse {
+                token1_reserve: if price.is_zero() {
+                    Uint128::zero()
+                } else {
+                    Uint128::new(1_000_000u128)
+                },
+                token1_denom: Denom::Native("ujuno".to_string()),
+                token2_reserve: price,
+                token2_denom: Denom::Cw20(Addr::unchecked("token")),
+                lp_token_supply: Uint128::new(1_000_000u128),
+                lp_token_address: "lp_token".to_string(),
+                owner: None,
+                lp_fee_percent: Decimal::permille(3),
+                protocol_fee_percent: Decimal::permille(2),
+                protocol_fee_recipient: "owner".to_string(),
+            })
         },
     );
     Box::new(contract)
@@ -73,7 +229,7 @@ pub fn contract_cw20() -> Box<dyn Contract<Empty>> {
 
 fn update_price(router: &mut App, config: ConfigResponse, price: Uint128, sender: &Addr) {
     let update_price_msg: CosmosMsg = CosmosMsg::Wasm(WasmMsg::Execute {
-        contract_addr: config.fast_oracle_addr.to_string(),
+        contract_addr: config.oracles[0].addr().to_string(),
         msg: to_binary(&FastOracleExecuteMsg::Update { price }).unwrap(),
         funds: vec![],
     });
@@ -98,6 +254,18 @@ fn start_next_round(router: &mut App, prediction_market_addr: &Addr, sender: &Ad
     router
         .execute_multi(sender.clone(), [start_live_round_msg].to_vec())
         .unwrap();
+    assert_solvent(router, prediction_market_addr);
+}
+
+/* Invariant: the contract can always pay out everything it owes */
+fn assert_solvent(router: &App, prediction_market_addr: &Addr) {
+    let solvency: SolvencyResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Solvency {})
+        .unwrap();
+    assert!(solvency.is_solvent, "contract is insolvent: {:?}", solvency);
+    /* Nothing else ever sends tokens to the contract, so nothing should be unaccounted for */
+    assert_eq!(solvency.token_balance, solvency.liabilities);
 }
 
 fn init_fast_oracle_contract(router: &mut App, owner: &Addr) -> Addr {
@@ -109,7 +277,11 @@ fn init_fast_oracle_contract(router: &mut App, owner: &Addr) -> Addr {
         .instantiate_contract(
             fast_oracle_code_id,
             Addr::unchecked("owner"),
-            &FastOracleInstantiateMsg {},
+            &FastOracleInstantiateMsg {
+                owner: None,
+                feeders: vec![],
+                price: Some(Uint128::new(1_000_000u128)),
+            },
             &[],
             "fast_oracle",
             Some(owner.to_string()),
@@ -117,7 +289,7 @@ fn init_fast_oracle_contract(router: &mut App, owner: &Addr) -> Addr {
         .unwrap()
 }
 
-fn init_cw20_Contract(router: &mut App, owner: &Addr) -> Addr {
+fn init_cw20_contract(router: &mut App, owner: &Addr) -> Addr {
     // println!("prediction_market_code_id, {:?}", prediction_market_code_id);
 
     let msg = Cw20InstantiateMsg {
@@ -174,9 +346,16 @@ fn create_prediction_market(router: &mut App, owner: &Addr, config: Config) -> A
     };
 
     let fast_oracle_addr: Addr = init_fast_oracle_contract(router, owner);
-    let cw20_addr: Addr = init_cw20_Contract(router, owner);
+    let cw20_addr: Addr = init_cw20_contract(router, owner);
 
-    msg.config.fast_oracle_addr = fast_oracle_addr;
+    /* Only the placeholder is replaced, tests with other oracles bring their own mocks */
+    for oracle in msg.config.oracles.iter_mut() {
+        if let OracleSource::FastOracle { addr, .. } = oracle {
+            if addr.as_str() == "fast_oracle" {
+                *addr = fast_oracle_addr.clone();
+            }
+        }
+    }
     msg.config.token_addr = cw20_addr;
 
     router
@@ -210,46 +389,96 @@ fn execute_bet(
         .unwrap(),
         funds: vec![],
     });
-    let bet_msg: CosmosMsg;
-    match direction {
-        Direction::Bear => {
-            bet_msg = CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: prediction_market_addr.to_string(),
-                msg: to_binary(&ExecuteMsg::BetBear { amount, round_id }).unwrap(),
-                funds: vec![],
-            });
-        }
-        Direction::Bull => {
-            bet_msg = CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: prediction_market_addr.to_string(),
-                msg: to_binary(&ExecuteMsg::BetBull { amount, round_id }).unwrap(),
-                funds: vec![],
-            });
-        }
-    }
+    let bet_msg: CosmosMsg = match direction {
+        Direction::Bear => CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: prediction_market_addr.to_string(),
+            msg: to_binary(&ExecuteMsg::BetBear {
+                amount,
+                round_id,
+                player: None,
+            })
+            .unwrap(),
+            funds: vec![],
+        }),
+        Direction::Bull => CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: prediction_market_addr.to_string(),
+            msg: to_binary(&ExecuteMsg::BetBull {
+                amount,
+                round_id,
+                player: None,
+            })
+            .unwrap(),
+            funds: vec![],
+        }),
+    };
 
     router
         .execute_multi(user, [increase_allowance_msg, bet_msg].to_vec())
         .unwrap();
+    assert_solvent(router, prediction_market_addr);
 }
 
-#[test]
-
-fn test_bet() {
-    let mut router = mock_app();
-    let owner = Addr::unchecked("owner");
-
-    let default_config: Config = Config {
+fn default_config() -> Config {
+    Config {
         next_round_seconds: Uint128::new(600u128),
-        fast_oracle_addr: Addr::unchecked("fast_oracle"),
+        oracles: vec![OracleSource::FastOracle {
+            addr: Addr::unchecked("fast_oracle"),
+            decimals: 6,
+        }],
+        oracle_quorum: 1,
+        max_oracle_deviation: Uint128::new(500u128),
+        fallback_oracles: vec![],
         minimum_bet: Uint128::new(1u128),
         burn_fee: Uint128::new(100u128),
         gaming_fee: Uint128::new(200u128),
         token_addr: Addr::unchecked("token_contract"),
-    };
+        cancel_penalty: Uint128::new(500u128),
+        cancel_buffer_seconds: Uint128::new(60u128),
+        maximum_bet: None,
+        maximum_player_round_bet: None,
+        max_pool_ratio: None,
+        bet_lock_buffer_seconds: Uint128::new(30u128),
+        tie_policy: TiePolicy::Refund,
+        treasury_addr: Addr::unchecked("treasury"),
+        claim_window_seconds: None,
+        expiry_destination: ExpiryDestination::Treasury,
+        push_settlement: PushSettlement::Off,
+        settlement_batch_size: 10,
+        strategy_batch_size: 10,
+        referral_share: Uint128::new(2500u128),
+        fee_distribution: FeeDistribution {
+            staker_share: Uint128::new(5000u128),
+            treasury_share: Uint128::new(3000u128),
+            burn_share: Uint128::new(2000u128),
+        },
+        staking_rewards_addr: None,
+        fee_tiers: vec![],
+        volume_window_seconds: Uint128::new(3600u128),
+        staking_addr: None,
+        fee_mode: FeeMode::OnStake,
+    }
+}
+
+fn query_balance(router: &App, token_addr: &Addr, address: &str) -> Uint128 {
+    let balance: BalanceResponse = router
+        .wrap()
+        .query_wasm_smart(
+            token_addr.to_string(),
+            &Cw20QueryMsg::Balance {
+                address: address.to_string(),
+            },
+        )
+        .unwrap();
+    balance.balance
+}
+
+#[test]
+
+fn test_bet() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
 
-    let prediction_market_addr =
-        create_prediction_market(&mut router, &owner, default_config.clone());
+    let prediction_market_addr = create_prediction_market(&mut router, &owner, default_config());
 
     start_next_round(&mut router, &prediction_market_addr, &owner);
 
@@ -285,11 +514,11 @@ fn test_bet() {
     update_price(&mut router, config, Uint128::new(100000), &owner);
     start_next_round(&mut router, &prediction_market_addr, &owner);
 
-    let status: StatusResponse = router
+    let _status: StatusResponse = router
         .wrap()
         .query_wasm_smart(prediction_market_addr.clone(), &QueryMsg::Status {})
         .unwrap();
-    // println!("status {:?}", status);
+    // println!("status {:?}", _status);
 
     let config: ConfigResponse = router
         .wrap()
@@ -355,23 +584,33 @@ fn test_bet() {
 
     let claim_msg: CosmosMsg = CosmosMsg::Wasm(WasmMsg::Execute {
         contract_addr: prediction_market_addr.to_string(),
-        msg: to_binary(&ExecuteMsg::CollectWinnings {}).unwrap(),
+        msg: to_binary(&ExecuteMsg::CollectWinnings {
+            player: None,
+            recipient: None,
+        })
+        .unwrap(),
         funds: vec![],
     });
 
     router
         .execute_multi(Addr::unchecked("user1"), [claim_msg].to_vec())
         .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
 
     let claim_msg: CosmosMsg = CosmosMsg::Wasm(WasmMsg::Execute {
         contract_addr: prediction_market_addr.to_string(),
-        msg: to_binary(&ExecuteMsg::CollectWinnings {}).unwrap(),
+        msg: to_binary(&ExecuteMsg::CollectWinnings {
+            player: None,
+            recipient: None,
+        })
+        .unwrap(),
         funds: vec![],
     });
 
     router
         .execute_multi(Addr::unchecked("user2"), [claim_msg].to_vec())
         .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
 
     //----------------------------------------------check balance after the claim----------------------------------------------
 
@@ -411,3 +650,3564 @@ fn test_bet() {
 
     //------------------------------------------------Test Distribute Reward--------------------------------------------------------------------//
 }
+
+#[test]
+fn test_cancel_and_switch_bet() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let prediction_market_addr = create_prediction_market(&mut router, &owner, default_config());
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+
+    execute_bet(
+        &mut router,
+        Addr::unchecked("user1"),
+        Uint128::new(100),
+        Direction::Bear,
+        &config.token_addr,
+        &prediction_market_addr,
+        Uint128::zero(),
+    );
+    execute_bet(
+        &mut router,
+        Addr::unchecked("user2"),
+        Uint128::new(100),
+        Direction::Bull,
+        &config.token_addr,
+        &prediction_market_addr,
+        Uint128::zero(),
+    );
+
+    /* 100 gross -> 98 staked after the 2% gaming fee; cancelling keeps 5% of that */
+    router
+        .execute_contract(
+            Addr::unchecked("user1"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CancelBet {
+                round_id: Uint128::zero(),
+            },
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+    assert_eq!(
+        query_balance(&router, &config.token_addr, "user1"),
+        Uint128::new(900 + 94)
+    );
+
+    router
+        .execute_contract(
+            Addr::unchecked("user2"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::SwitchDirection {
+                round_id: Uint128::zero(),
+            },
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+
+    let position: MyCurrentPositionResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.clone(),
+            &QueryMsg::MyCurrentPosition {
+                address: "user2".to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(position.next_bull_amount, Uint128::zero());
+    assert_eq!(position.next_bear_amount, Uint128::new(94));
+
+    /* Inside the buffer before open_time nothing can be changed anymore */
+    router.update_block(|block| {
+        block.time = block.time.plus_seconds(550);
+    });
+    let err = router
+        .execute_contract(
+            Addr::unchecked("user2"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CancelBet {
+                round_id: Uint128::zero(),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::CancelWindowClosed {}
+    );
+
+    /* The penalty can't take more than the stake */
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::UpdateConfig {
+                config: Box::new(Config {
+                    cancel_penalty: Uint128::new(10_001u128),
+                    ..config
+                }),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(err.root_cause().to_string().contains("cancel penalty"));
+}
+
+#[test]
+fn test_bet_caps() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let config = Config {
+        maximum_bet: Some(Uint128::new(300)),
+        maximum_player_round_bet: Some(Uint128::new(400)),
+        max_pool_ratio: Some(Decimal::percent(300)),
+        ..default_config()
+    };
+    let prediction_market_addr = create_prediction_market(&mut router, &owner, config);
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+
+    let bet = |router: &mut App, user: &str, amount: u128, direction: Direction| {
+        let amount = Uint128::new(amount);
+        router
+            .execute_contract(
+                Addr::unchecked(user),
+                config.token_addr.clone(),
+                &Cw20ExecuteMsg::IncreaseAllowance {
+                    spender: prediction_market_addr.to_string(),
+                    amount,
+                    expires: None,
+                },
+                &[],
+            )
+            .unwrap();
+        let msg = match direction {
+            Direction::Bull => ExecuteMsg::BetBull {
+                round_id: Uint128::zero(),
+                amount,
+                player: None,
+            },
+            Direction::Bear => ExecuteMsg::BetBear {
+                round_id: Uint128::zero(),
+                amount,
+                player: None,
+            },
+        };
+        router
+            .execute_contract(
+                Addr::unchecked(user),
+                prediction_market_addr.clone(),
+                &msg,
+                &[],
+            )
+            .map_err(|e| e.downcast::<ContractError>().unwrap())
+    };
+
+    assert_eq!(
+        bet(&mut router, "user1", 301, Direction::Bull).unwrap_err(),
+        ContractError::ExceedMaximumBet {
+            maximum: Uint128::new(300)
+        }
+    );
+
+    /* First bet on an empty book is always accepted */
+    bet(&mut router, "user1", 300, Direction::Bull).unwrap();
+    bet(&mut router, "user2", 100, Direction::Bear).unwrap();
+
+    /* 294 + 98 bull against 98 bear would be 4x */
+    assert_eq!(
+        bet(&mut router, "user3", 100, Direction::Bull).unwrap_err(),
+        ContractError::ExceedPoolRatio {
+            max_ratio: Decimal::percent(300)
+        }
+    );
+
+    bet(&mut router, "user3", 200, Direction::Bear).unwrap();
+
+    /* Topping up counts towards the per-round limit: 294 + 147 > 400 */
+    assert_eq!(
+        bet(&mut router, "user1", 150, Direction::Bull).unwrap_err(),
+        ContractError::ExceedPlayerRoundMaximum {
+            maximum: Uint128::new(400)
+        }
+    );
+    bet(&mut router, "user1", 100, Direction::Bull).unwrap();
+
+    let switch = |router: &mut App, user: &str| {
+        router
+            .execute_contract(
+                Addr::unchecked(user),
+                prediction_market_addr.clone(),
+                &ExecuteMsg::SwitchDirection {
+                    round_id: Uint128::zero(),
+                },
+                &[],
+            )
+            .map_err(|e| e.downcast::<ContractError>().unwrap())
+    };
+
+    /* Switching is held to the ratio too: 392 + 187 bull against 98 bear */
+    assert_eq!(
+        switch(&mut router, "user3").unwrap_err(),
+        ContractError::ExceedPoolRatio {
+            max_ratio: Decimal::percent(300)
+        }
+    );
+    /* 392 + 94 bull against 196 bear */
+    switch(&mut router, "user2").unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+}
+
+#[test]
+fn test_bet_lock_buffer() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let prediction_market_addr = create_prediction_market(&mut router, &owner, default_config());
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+
+    /* Round 0 opens at 1200, betting is locked from 1170 */
+    router.update_block(|block| {
+        block.time = Timestamp::from_seconds(1171);
+    });
+    router
+        .execute_contract(
+            Addr::unchecked("user1"),
+            config.token_addr.clone(),
+            &Cw20ExecuteMsg::IncreaseAllowance {
+                spender: prediction_market_addr.to_string(),
+                amount: Uint128::new(100),
+                expires: None,
+            },
+            &[],
+        )
+        .unwrap();
+    router
+        .execute_contract(
+            Addr::unchecked("user1"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::BetBull {
+                round_id: Uint128::zero(),
+                amount: Uint128::new(100),
+                player: None,
+            },
+            &[],
+        )
+        .unwrap_err();
+
+    /* The keeper lands late, the round still opens at its scheduled time but runs a full round */
+    router.update_block(|block| {
+        block.time = Timestamp::from_seconds(1250);
+    });
+    router
+        .execute_contract(
+            owner.clone(),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CloseRound {},
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+
+    let status: StatusResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.clone(), &QueryMsg::Status {})
+        .unwrap();
+    let live_round = status.live_round.unwrap();
+    assert_eq!(live_round.open_time, Timestamp::from_seconds(1200));
+    assert_eq!(live_round.close_time, Timestamp::from_seconds(1850));
+}
+
+#[test]
+fn test_late_keeper() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let prediction_market_addr = create_prediction_market(&mut router, &owner, default_config());
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+
+    /* Round 0 was due to open at 1200 and close at 1800, the keeper only shows up at 1900 */
+    router.update_block(|block| {
+        block.time = Timestamp::from_seconds(1900);
+        block.height += 1;
+    });
+    router
+        .execute_contract(
+            owner.clone(),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CloseRound {},
+            &[],
+        )
+        .unwrap();
+
+    let status: StatusResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.clone(), &QueryMsg::Status {})
+        .unwrap();
+    let live_round = status.live_round.unwrap();
+    assert_eq!(live_round.open_time, Timestamp::from_seconds(1200));
+    assert_eq!(live_round.close_time, Timestamp::from_seconds(2500));
+    assert_eq!(
+        status.bidding_round.unwrap().open_time,
+        Timestamp::from_seconds(2500)
+    );
+
+    /* The next round still takes bets */
+    execute_bet(
+        &mut router,
+        Addr::unchecked("user1"),
+        Uint128::new(100),
+        Direction::Bull,
+        &config.token_addr,
+        &prediction_market_addr,
+        Uint128::new(1),
+    );
+
+    /* And the live round doesn't close early */
+    router.update_block(|block| {
+        block.time = Timestamp::from_seconds(1910);
+        block.height += 1;
+    });
+    router
+        .execute_contract(
+            owner.clone(),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CloseRound {},
+            &[],
+        )
+        .unwrap();
+    let status: StatusResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.clone(), &QueryMsg::Status {})
+        .unwrap();
+    assert_eq!(status.live_round.unwrap().id, Uint128::zero());
+    assert_solvent(&router, &prediction_market_addr);
+}
+
+#[test]
+fn test_tie_rollover() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let config = Config {
+        tie_policy: TiePolicy::Rollover,
+        ..default_config()
+    };
+    let prediction_market_addr = create_prediction_market(&mut router, &owner, config);
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+
+    for (user, direction, round_id) in [
+        ("user1", Direction::Bull, 0u128),
+        ("user2", Direction::Bear, 0u128),
+    ] {
+        execute_bet(
+            &mut router,
+            Addr::unchecked(user),
+            Uint128::new(100),
+            direction,
+            &config.token_addr,
+            &prediction_market_addr,
+            Uint128::new(round_id),
+        );
+    }
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    for (user, direction) in [("user3", Direction::Bull), ("user4", Direction::Bear)] {
+        execute_bet(
+            &mut router,
+            Addr::unchecked(user),
+            Uint128::new(100),
+            direction,
+            &config.token_addr,
+            &prediction_market_addr,
+            Uint128::new(1),
+        );
+    }
+
+    /* Round 0 closes on an unchanged price, round 1 goes up */
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    update_price(&mut router, config.clone(), Uint128::new(2_000_000), &owner);
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    let round: RoundResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.clone(),
+            &QueryMsg::FinishedRound {
+                round_id: Uint128::zero(),
+            },
+        )
+        .unwrap();
+    assert_eq!(round.tie_policy, Some(TiePolicy::Rollover));
+
+    let pending = |router: &App, player: &str| -> Uint128 {
+        let resp: PendingRewardResponse = router
+            .wrap()
+            .query_wasm_smart(
+                prediction_market_addr.clone(),
+                &QueryMsg::MyPendingReward {
+                    player: Addr::unchecked(player),
+                },
+            )
+            .unwrap();
+        resp.pending_reward
+    };
+    assert_eq!(pending(&router, "user1"), Uint128::zero());
+    assert_eq!(pending(&router, "user2"), Uint128::zero());
+    assert_eq!(pending(&router, "user3"), Uint128::new(392));
+    assert_eq!(pending(&router, "user4"), Uint128::zero());
+
+    router
+        .execute_contract(
+            Addr::unchecked("user3"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CollectWinnings {
+                player: None,
+                recipient: None,
+            },
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+    assert_eq!(
+        query_balance(&router, &config.token_addr, "user3"),
+        Uint128::new(900 + 392)
+    );
+}
+
+#[test]
+fn test_claimable_rounds() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let prediction_market_addr = create_prediction_market(&mut router, &owner, default_config());
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+
+    for (user, direction) in [("user1", Direction::Bull), ("user2", Direction::Bear)] {
+        execute_bet(
+            &mut router,
+            Addr::unchecked(user),
+            Uint128::new(100),
+            direction,
+            &config.token_addr,
+            &prediction_market_addr,
+            Uint128::zero(),
+        );
+    }
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    execute_bet(
+        &mut router,
+        Addr::unchecked("user1"),
+        Uint128::new(50),
+        Direction::Bull,
+        &config.token_addr,
+        &prediction_market_addr,
+        Uint128::new(1),
+    );
+    update_price(&mut router, config.clone(), Uint128::new(2_000_000), &owner);
+
+    /* Round 0 finishes while user1 still has a bet in the live round 1 */
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    let claimable: ClaimableRoundsResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.clone(),
+            &QueryMsg::ClaimableRounds {
+                player: Addr::unchecked("user1"),
+            },
+        )
+        .unwrap();
+    assert_eq!(claimable.claimable_rounds.len(), 1);
+    assert_eq!(claimable.claimable_rounds[0].round_id, Uint128::zero());
+    assert_eq!(claimable.claimable_rounds[0].payout, Uint128::new(196));
+    assert_eq!(claimable.pending_reward, Uint128::new(196));
+
+    router
+        .execute_contract(
+            Addr::unchecked("user1"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CollectWinnings {
+                player: None,
+                recipient: None,
+            },
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+    assert_eq!(
+        query_balance(&router, &config.token_addr, "user1"),
+        Uint128::new(850 + 196)
+    );
+}
+
+#[test]
+fn test_dust_sweep() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let prediction_market_addr = create_prediction_market(&mut router, &owner, default_config());
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+
+    for (user, amount, direction) in [
+        ("user1", 100u128, Direction::Bull),
+        ("user2", 50u128, Direction::Bull),
+        ("user3", 100u128, Direction::Bear),
+    ] {
+        execute_bet(
+            &mut router,
+            Addr::unchecked(user),
+            Uint128::new(amount),
+            direction,
+            &config.token_addr,
+            &prediction_market_addr,
+            Uint128::zero(),
+        );
+    }
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    update_price(&mut router, config.clone(), Uint128::new(2_000_000), &owner);
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    /* 245 pooled, bulls get 163 + 81. The dust is only booked once both are settled */
+    let query_round = |router: &App| -> RoundResponse {
+        router
+            .wrap()
+            .query_wasm_smart(
+                prediction_market_addr.clone(),
+                &QueryMsg::FinishedRound {
+                    round_id: Uint128::zero(),
+                },
+            )
+            .unwrap()
+    };
+    assert_eq!(query_round(&router).dust, Uint128::zero());
+    let solvency: SolvencyResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.clone(), &QueryMsg::Solvency {})
+        .unwrap();
+    assert_eq!(solvency.dust, Uint128::new(1));
+    assert_solvent(&router, &prediction_market_addr);
+    router
+        .execute_contract(
+            Addr::unchecked("treasury"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::SweepDust {},
+            &[],
+        )
+        .unwrap_err();
+
+    for user in ["user1", "user2"] {
+        assert_eq!(query_round(&router).dust, Uint128::zero());
+        router
+            .execute_contract(
+                Addr::unchecked(user),
+                prediction_market_addr.clone(),
+                &ExecuteMsg::CollectWinnings {
+                    player: None,
+                    recipient: None,
+                },
+                &[],
+            )
+            .unwrap();
+        assert_solvent(&router, &prediction_market_addr);
+    }
+    assert_eq!(query_round(&router).dust, Uint128::new(1));
+
+    router
+        .execute_contract(
+            Addr::unchecked("user1"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::SweepDust {},
+            &[],
+        )
+        .unwrap_err();
+    router
+        .execute_contract(
+            Addr::unchecked("treasury"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::SweepDust {},
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+    assert_eq!(
+        query_balance(&router, &config.token_addr, "treasury"),
+        Uint128::new(1)
+    );
+}
+
+#[test]
+fn test_expire_unclaimed_winnings() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let config = Config {
+        claim_window_seconds: Some(Uint128::new(1000)),
+        ..default_config()
+    };
+    let prediction_market_addr = create_prediction_market(&mut router, &owner, config);
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+
+    for (user, direction) in [("user1", Direction::Bull), ("user2", Direction::Bear)] {
+        execute_bet(
+            &mut router,
+            Addr::unchecked(user),
+            Uint128::new(100),
+            direction,
+            &config.token_addr,
+            &prediction_market_addr,
+            Uint128::zero(),
+        );
+    }
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    update_price(&mut router, config.clone(), Uint128::new(2_000_000), &owner);
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    /* Round 0 closed at 1800, so it expires at 2800 */
+    let expiring: ExpiringRoundsResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.clone(),
+            &QueryMsg::ExpiringRounds {
+                player: Addr::unchecked("user1"),
+                within_seconds: 1000,
+            },
+        )
+        .unwrap();
+    assert_eq!(expiring.expiring_rounds.len(), 1);
+    assert_eq!(expiring.expiring_rounds[0].payout, Uint128::new(196));
+    assert_eq!(
+        expiring.expiring_rounds[0].expires_at,
+        Timestamp::from_seconds(2800)
+    );
+
+    let expire_msg = ExecuteMsg::ExpireRound {
+        round_id: Uint128::zero(),
+        limit: 1,
+    };
+    let err = router
+        .execute_contract(
+            Addr::unchecked("user3"),
+            prediction_market_addr.clone(),
+            &expire_msg,
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::ClaimWindowOpen {}
+    );
+
+    router.update_block(|block| {
+        block.time = Timestamp::from_seconds(2800);
+    });
+    router
+        .execute_contract(
+            Addr::unchecked("user3"),
+            prediction_market_addr.clone(),
+            &expire_msg,
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+
+    assert_eq!(
+        query_balance(&router, &config.token_addr, "treasury"),
+        Uint128::new(196)
+    );
+
+    /* One bet per call, the round is expired with its last bet */
+    let query_round = |router: &App| -> RoundResponse {
+        router
+            .wrap()
+            .query_wasm_smart(
+                prediction_market_addr.clone(),
+                &QueryMsg::FinishedRound {
+                    round_id: Uint128::zero(),
+                },
+            )
+            .unwrap()
+    };
+    assert!(!query_round(&router).expired);
+    router
+        .execute_contract(
+            Addr::unchecked("user3"),
+            prediction_market_addr.clone(),
+            &expire_msg,
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+    assert!(query_round(&router).expired);
+    let err = router
+        .execute_contract(
+            Addr::unchecked("user3"),
+            prediction_market_addr.clone(),
+            &expire_msg,
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::RoundExpired {}
+    );
+    router
+        .execute_contract(
+            Addr::unchecked("user1"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CollectWinnings {
+                player: None,
+                recipient: None,
+            },
+            &[],
+        )
+        .unwrap_err();
+}
+
+#[test]
+fn test_collect_winnings_for_player() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let prediction_market_addr = create_prediction_market(&mut router, &owner, default_config());
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+
+    for (user, direction) in [
+        ("user1", Direction::Bull),
+        ("user2", Direction::Bull),
+        ("user3", Direction::Bear),
+    ] {
+        execute_bet(
+            &mut router,
+            Addr::unchecked(user),
+            Uint128::new(100),
+            direction,
+            &config.token_addr,
+            &prediction_market_addr,
+            Uint128::zero(),
+        );
+    }
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    update_price(&mut router, config.clone(), Uint128::new(2_000_000), &owner);
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    /* A relayer can't redirect someone else's payout */
+    router
+        .execute_contract(
+            Addr::unchecked("relayer"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CollectWinnings {
+                player: Some("user1".to_string()),
+                recipient: Some("relayer".to_string()),
+            },
+            &[],
+        )
+        .unwrap_err();
+
+    router
+        .execute_contract(
+            Addr::unchecked("relayer"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CollectWinnings {
+                player: Some("user1".to_string()),
+                recipient: None,
+            },
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+    assert_eq!(
+        query_balance(&router, &config.token_addr, "user1"),
+        Uint128::new(900 + 147)
+    );
+
+    router
+        .execute_contract(
+            Addr::unchecked("user2"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CollectWinnings {
+                player: None,
+                recipient: Some("cold_wallet".to_string()),
+            },
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+    assert_eq!(
+        query_balance(&router, &config.token_addr, "cold_wallet"),
+        Uint128::new(147)
+    );
+}
+
+#[test]
+fn test_bet_from_winnings() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let prediction_market_addr = create_prediction_market(&mut router, &owner, default_config());
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+
+    for (user, direction) in [("user1", Direction::Bull), ("user2", Direction::Bear)] {
+        execute_bet(
+            &mut router,
+            Addr::unchecked(user),
+            Uint128::new(100),
+            direction,
+            &config.token_addr,
+            &prediction_market_addr,
+            Uint128::zero(),
+        );
+    }
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    update_price(&mut router, config.clone(), Uint128::new(2_000_000), &owner);
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    /* user1 won 196 and re-bets 150 of it on round 2 */
+    let bet_from_winnings = |amount: u128| ExecuteMsg::BetFromWinnings {
+        round_id: Uint128::new(2),
+        direction: Direction::Bear,
+        amount: Uint128::new(amount),
+    };
+    let err = router
+        .execute_contract(
+            Addr::unchecked("user1"),
+            prediction_market_addr.clone(),
+            &bet_from_winnings(200),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::InsufficientFunds {}
+    );
+
+    router
+        .execute_contract(
+            Addr::unchecked("user1"),
+            prediction_market_addr.clone(),
+            &bet_from_winnings(150),
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+
+    assert_eq!(
+        query_balance(&router, &config.token_addr, "user1"),
+        Uint128::new(900 + 46)
+    );
+    let position: MyCurrentPositionResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.clone(),
+            &QueryMsg::MyCurrentPosition {
+                address: "user1".to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(position.next_bear_amount, Uint128::new(147));
+}
+
+fn query_player_balance(router: &App, prediction_market_addr: &Addr, player: &str) -> Uint128 {
+    let resp: PlayerBalanceResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::Balance {
+                player: Addr::unchecked(player),
+            },
+        )
+        .unwrap();
+    resp.balance
+}
+
+#[test]
+fn test_balance_ledger() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let prediction_market_addr = create_prediction_market(&mut router, &owner, default_config());
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+
+    router
+        .execute_contract(
+            Addr::unchecked("user1"),
+            config.token_addr.clone(),
+            &Cw20ExecuteMsg::IncreaseAllowance {
+                spender: prediction_market_addr.to_string(),
+                amount: Uint128::new(500),
+                expires: None,
+            },
+            &[],
+        )
+        .unwrap();
+    router
+        .execute_contract(
+            Addr::unchecked("user1"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::Deposit {
+                amount: Uint128::new(500),
+            },
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+
+    router
+        .execute_contract(
+            Addr::unchecked("user1"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::BetFromBalance {
+                round_id: Uint128::zero(),
+                direction: Direction::Bull,
+                amount: Uint128::new(100),
+                player: None,
+            },
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+    assert_eq!(
+        query_player_balance(&router, &prediction_market_addr, "user1"),
+        Uint128::new(400)
+    );
+
+    execute_bet(
+        &mut router,
+        Addr::unchecked("user2"),
+        Uint128::new(100),
+        Direction::Bear,
+        &config.token_addr,
+        &prediction_market_addr,
+        Uint128::zero(),
+    );
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    update_price(&mut router, config.clone(), Uint128::new(2_000_000), &owner);
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    /* Credited at close without a claim */
+    assert_eq!(
+        query_player_balance(&router, &prediction_market_addr, "user1"),
+        Uint128::new(400 + 196)
+    );
+
+    router
+        .execute_contract(
+            Addr::unchecked("user1"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::Withdraw {
+                amount: Uint128::new(597),
+            },
+            &[],
+        )
+        .unwrap_err();
+    router
+        .execute_contract(
+            Addr::unchecked("user1"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::Withdraw {
+                amount: Uint128::new(596),
+            },
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+    assert_eq!(
+        query_balance(&router, &config.token_addr, "user1"),
+        Uint128::new(500 + 596)
+    );
+}
+
+#[test]
+fn test_push_settlement() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let prediction_market_addr = create_prediction_market(
+        &mut router,
+        &owner,
+        Config {
+            push_settlement: PushSettlement::Transfer,
+            settlement_batch_size: 1,
+            ..default_config()
+        },
+    );
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+
+    for (user, direction) in [
+        ("user1", Direction::Bull),
+        ("user2", Direction::Bull),
+        ("user3", Direction::Bull),
+        ("user4", Direction::Bear),
+    ] {
+        execute_bet(
+            &mut router,
+            Addr::unchecked(user),
+            Uint128::new(100),
+            direction,
+            &config.token_addr,
+            &prediction_market_addr,
+            Uint128::zero(),
+        );
+    }
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    update_price(&mut router, config.clone(), Uint128::new(2_000_000), &owner);
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    /* Only the first winner is paid by the close itself */
+    assert_eq!(
+        query_balance(&router, &config.token_addr, "user1"),
+        Uint128::new(900 + 130)
+    );
+    assert_eq!(
+        query_balance(&router, &config.token_addr, "user2"),
+        Uint128::new(900)
+    );
+    let pending: PendingSettlementsResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::PendingSettlements {},
+        )
+        .unwrap();
+    assert_eq!(pending.pending_settlements.len(), 1);
+    assert_eq!(
+        pending.pending_settlements[0].last_player,
+        Some(Addr::unchecked("user1"))
+    );
+
+    router
+        .execute_contract(
+            Addr::unchecked("keeper"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::DistributeWinnings { limit: 10 },
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+
+    for user in ["user2", "user3"] {
+        assert_eq!(
+            query_balance(&router, &config.token_addr, user),
+            Uint128::new(900 + 130)
+        );
+    }
+    let pending: PendingSettlementsResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::PendingSettlements {},
+        )
+        .unwrap();
+    assert!(pending.pending_settlements.is_empty());
+
+    /* Nothing left to collect once pushed */
+    let pending_reward: PendingRewardResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::MyPendingReward {
+                player: Addr::unchecked("user3"),
+            },
+        )
+        .unwrap();
+    assert!(pending_reward.pending_reward.is_zero());
+}
+
+#[test]
+fn test_push_settlement_off() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let prediction_market_addr = create_prediction_market(
+        &mut router,
+        &owner,
+        Config {
+            settlement_batch_size: 1,
+            ..default_config()
+        },
+    );
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+
+    /* user1's bet isn't pushed, it must not use up the batch before user2's */
+    execute_bet(
+        &mut router,
+        Addr::unchecked("user1"),
+        Uint128::new(100),
+        Direction::Bull,
+        &config.token_addr,
+        &prediction_market_addr,
+        Uint128::zero(),
+    );
+    router
+        .execute_contract(
+            Addr::unchecked("user2"),
+            config.token_addr.clone(),
+            &Cw20ExecuteMsg::IncreaseAllowance {
+                spender: prediction_market_addr.to_string(),
+                amount: Uint128::new(100),
+                expires: None,
+            },
+            &[],
+        )
+        .unwrap();
+    router
+        .execute_contract(
+            Addr::unchecked("user2"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::Deposit {
+                amount: Uint128::new(100),
+            },
+            &[],
+        )
+        .unwrap();
+    router
+        .execute_contract(
+            Addr::unchecked("user2"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::BetFromBalance {
+                round_id: Uint128::zero(),
+                direction: Direction::Bull,
+                amount: Uint128::new(100),
+                player: None,
+            },
+            &[],
+        )
+        .unwrap();
+    execute_bet(
+        &mut router,
+        Addr::unchecked("user4"),
+        Uint128::new(100),
+        Direction::Bear,
+        &config.token_addr,
+        &prediction_market_addr,
+        Uint128::zero(),
+    );
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    update_price(&mut router, config.clone(), Uint128::new(2_000_000), &owner);
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    assert_eq!(
+        query_player_balance(&router, &prediction_market_addr, "user2"),
+        Uint128::new(147)
+    );
+    assert_eq!(
+        query_balance(&router, &config.token_addr, "user1"),
+        Uint128::new(900)
+    );
+    let pending: PendingSettlementsResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::PendingSettlements {},
+        )
+        .unwrap();
+    assert!(pending.pending_settlements.is_empty());
+
+    /* A round without balance-funded bets has nothing to push */
+    execute_bet(
+        &mut router,
+        Addr::unchecked("user1"),
+        Uint128::new(100),
+        Direction::Bull,
+        &config.token_addr,
+        &prediction_market_addr,
+        Uint128::new(2),
+    );
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    let pending: PendingSettlementsResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::PendingSettlements {},
+        )
+        .unwrap();
+    assert!(pending.pending_settlements.is_empty());
+    assert_solvent(&router, &prediction_market_addr);
+}
+
+fn query_strategy(router: &App, prediction_market_addr: &Addr, player: &str) -> StrategyResponse {
+    router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::Strategy {
+                player: Addr::unchecked(player),
+            },
+        )
+        .unwrap()
+}
+
+#[test]
+fn test_strategies() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let prediction_market_addr = create_prediction_market(&mut router, &owner, default_config());
+
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+
+    for user in ["user1", "user2"] {
+        router
+            .execute_contract(
+                Addr::unchecked(user),
+                config.token_addr.clone(),
+                &Cw20ExecuteMsg::IncreaseAllowance {
+                    spender: prediction_market_addr.to_string(),
+                    amount: Uint128::new(1000),
+                    expires: None,
+                },
+                &[],
+            )
+            .unwrap();
+    }
+    router
+        .execute_contract(
+            Addr::unchecked("user1"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CreateStrategy {
+                kind: StrategyKind::Fixed {
+                    direction: Direction::Bull,
+                },
+                amount: Uint128::new(100),
+                rounds: 2,
+                budget: Uint128::new(250),
+            },
+            &[],
+        )
+        .unwrap();
+    router
+        .execute_contract(
+            Addr::unchecked("user2"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CreateStrategy {
+                kind: StrategyKind::FollowWinner {},
+                amount: Uint128::new(100),
+                rounds: 5,
+                budget: Uint128::new(200),
+            },
+            &[],
+        )
+        .unwrap();
+    /* One strategy per player */
+    router
+        .execute_contract(
+            Addr::unchecked("user2"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CreateStrategy {
+                kind: StrategyKind::FollowWinner {},
+                amount: Uint128::new(100),
+                rounds: 1,
+                budget: Uint128::new(100),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert_solvent(&router, &prediction_market_addr);
+
+    /* Round 0 opens, only the fixed strategy has a side */
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    let strategy = query_strategy(&router, &prediction_market_addr, "user1")
+        .strategy
+        .unwrap();
+    assert_eq!(strategy.budget, Uint128::new(150));
+    assert_eq!(strategy.rounds_remaining, 1);
+    assert_eq!(
+        query_strategy(&router, &prediction_market_addr, "user2")
+            .strategy
+            .unwrap()
+            .budget,
+        Uint128::new(200)
+    );
+
+    execute_bet(
+        &mut router,
+        Addr::unchecked("user3"),
+        Uint128::new(100),
+        Direction::Bear,
+        &config.token_addr,
+        &prediction_market_addr,
+        Uint128::zero(),
+    );
+
+    /* Round 1 opens, the fixed strategy runs out of rounds and refunds its budget */
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    assert!(query_strategy(&router, &prediction_market_addr, "user1")
+        .strategy
+        .is_none());
+    assert_eq!(
+        query_player_balance(&router, &prediction_market_addr, "user1"),
+        Uint128::new(50)
+    );
+
+    /* Round 0 goes to the bulls, round 2 opens and the follower bets bull */
+    update_price(&mut router, config.clone(), Uint128::new(2_000_000), &owner);
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    assert_eq!(
+        query_player_balance(&router, &prediction_market_addr, "user1"),
+        Uint128::new(50 + 196)
+    );
+    let strategy = query_strategy(&router, &prediction_market_addr, "user2")
+        .strategy
+        .unwrap();
+    /* Rounds without a winner to follow are used up too */
+    assert_eq!(strategy.budget, Uint128::new(100));
+    assert_eq!(strategy.rounds_remaining, 2);
+
+    router
+        .execute_contract(
+            Addr::unchecked("user2"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::ModifyStrategy {
+                kind: None,
+                amount: Some(Uint128::new(50)),
+                rounds: None,
+                top_up: Some(Uint128::new(100)),
+            },
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+    assert_eq!(
+        query_strategy(&router, &prediction_market_addr, "user2")
+            .strategy
+            .unwrap()
+            .amount,
+        Uint128::new(50)
+    );
+
+    router
+        .execute_contract(
+            Addr::unchecked("user2"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CancelStrategy {},
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+    assert_eq!(
+        query_balance(&router, &config.token_addr, "user2"),
+        Uint128::new(900)
+    );
+    router
+        .execute_contract(
+            Addr::unchecked("user2"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CancelStrategy {},
+            &[],
+        )
+        .unwrap_err();
+}
+
+#[test]
+fn test_strategy_batches() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let prediction_market_addr = create_prediction_market(
+        &mut router,
+        &owner,
+        Config {
+            strategy_batch_size: 1,
+            ..default_config()
+        },
+    );
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+
+    for user in ["user1", "user2", "user3"] {
+        router
+            .execute_contract(
+                Addr::unchecked(user),
+                config.token_addr.clone(),
+                &Cw20ExecuteMsg::IncreaseAllowance {
+                    spender: prediction_market_addr.to_string(),
+                    amount: Uint128::new(1000),
+                    expires: None,
+                },
+                &[],
+            )
+            .unwrap();
+    }
+
+    /* The budget has to cover at least one bet */
+    let err = router
+        .execute_contract(
+            Addr::unchecked("user1"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CreateStrategy {
+                kind: StrategyKind::Fixed {
+                    direction: Direction::Bull,
+                },
+                amount: Uint128::new(100),
+                rounds: 3,
+                budget: Uint128::new(50),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(err.root_cause().to_string().contains("budget"));
+
+    for user in ["user1", "user2", "user3"] {
+        router
+            .execute_contract(
+                Addr::unchecked(user),
+                prediction_market_addr.clone(),
+                &ExecuteMsg::CreateStrategy {
+                    kind: StrategyKind::Fixed {
+                        direction: Direction::Bull,
+                    },
+                    amount: Uint128::new(100),
+                    rounds: 3,
+                    budget: Uint128::new(100),
+                },
+                &[],
+            )
+            .unwrap();
+    }
+
+    /* CloseRound only gets through one strategy, a keeper does the rest */
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    let status: StatusResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Status {})
+        .unwrap();
+    assert_eq!(status.bidding_round.unwrap().bull_amount, Uint128::new(98));
+    router
+        .execute_contract(
+            owner.clone(),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::RunStrategies { limit: 1 },
+            &[],
+        )
+        .unwrap();
+    let status: StatusResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Status {})
+        .unwrap();
+    assert_eq!(status.bidding_round.unwrap().bull_amount, Uint128::new(196));
+
+    /* Once betting is locked nobody can burn the rest of the strategies' rounds */
+    router.update_block(|block| {
+        block.time = block.time.plus_seconds(590);
+        block.height += 1;
+    });
+    router
+        .execute_contract(
+            Addr::unchecked("anyone"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::RunStrategies { limit: 10 },
+            &[],
+        )
+        .unwrap_err();
+    let strategy = query_strategy(&router, &prediction_market_addr, "user3")
+        .strategy
+        .unwrap();
+    assert_eq!(strategy.rounds_remaining, 3);
+
+    /* Spent budgets can't bet again */
+    for user in ["user1", "user2"] {
+        assert!(query_strategy(&router, &prediction_market_addr, user)
+            .strategy
+            .is_none());
+    }
+    assert_solvent(&router, &prediction_market_addr);
+}
+
+#[test]
+fn test_operator_bets() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let operator = Addr::unchecked("hot_key");
+    let prediction_market_addr = create_prediction_market(&mut router, &owner, default_config());
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+
+    router
+        .execute_contract(
+            Addr::unchecked("user1"),
+            config.token_addr.clone(),
+            &Cw20ExecuteMsg::IncreaseAllowance {
+                spender: prediction_market_addr.to_string(),
+                amount: Uint128::new(1000),
+                expires: None,
+            },
+            &[],
+        )
+        .unwrap();
+    let bet_for_user1 = ExecuteMsg::BetBull {
+        round_id: Uint128::zero(),
+        amount: Uint128::new(100),
+        player: Some("user1".to_string()),
+    };
+
+    /* Not approved yet */
+    let err = router
+        .execute_contract(
+            operator.clone(),
+            prediction_market_addr.clone(),
+            &bet_for_user1,
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+
+    router
+        .execute_contract(
+            Addr::unchecked("user1"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::ApproveOperator {
+                operator: operator.to_string(),
+                spend_limit: Uint128::new(150),
+                expires: Some(router.block_info().time.plus_seconds(300)),
+            },
+            &[],
+        )
+        .unwrap();
+
+    router
+        .execute_contract(
+            operator.clone(),
+            prediction_market_addr.clone(),
+            &bet_for_user1,
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+    /* The player's tokens are staked, the operator holds none */
+    assert_eq!(
+        query_balance(&router, &config.token_addr, "user1"),
+        Uint128::new(900)
+    );
+    let position: MyCurrentPositionResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::MyCurrentPosition {
+                address: "user1".to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(position.next_bull_amount, Uint128::new(98));
+
+    let operators: OperatorsResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::Operators {
+                player: Addr::unchecked("user1"),
+            },
+        )
+        .unwrap();
+    assert_eq!(operators.operators[0].spend_limit, Uint128::new(50));
+
+    let err = router
+        .execute_contract(
+            operator.clone(),
+            prediction_market_addr.clone(),
+            &bet_for_user1,
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::ExceedOperatorSpendLimit {
+            remaining: Uint128::new(50)
+        },
+        err.downcast().unwrap()
+    );
+
+    router.update_block(|block| block.time = block.time.plus_seconds(300));
+    let err = router
+        .execute_contract(
+            operator.clone(),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::BetBull {
+                round_id: Uint128::zero(),
+                amount: Uint128::new(10),
+                player: Some("user1".to_string()),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::OperatorExpired {}, err.downcast().unwrap());
+
+    router
+        .execute_contract(
+            Addr::unchecked("user1"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::RevokeOperator {
+                operator: operator.to_string(),
+            },
+            &[],
+        )
+        .unwrap();
+    let operators: OperatorsResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::Operators {
+                player: Addr::unchecked("user1"),
+            },
+        )
+        .unwrap();
+    assert!(operators.operators.is_empty());
+}
+
+#[test]
+fn test_referrals() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let prediction_market_addr = create_prediction_market(&mut router, &owner, default_config());
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+
+    for user in ["user1", "user2"] {
+        router
+            .execute_contract(
+                Addr::unchecked(user),
+                prediction_market_addr.clone(),
+                &ExecuteMsg::RegisterReferrer {
+                    referrer: "user3".to_string(),
+                },
+                &[],
+            )
+            .unwrap();
+    }
+    let err = router
+        .execute_contract(
+            Addr::unchecked("user1"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::RegisterReferrer {
+                referrer: "user4".to_string(),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::ReferrerAlreadySet {},
+        err.downcast().unwrap()
+    );
+    router
+        .execute_contract(
+            Addr::unchecked("user4"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::RegisterReferrer {
+                referrer: "user4".to_string(),
+            },
+            &[],
+        )
+        .unwrap_err();
+
+    /* 2 fee on each bet, a quarter of it for the referrer */
+    execute_bet(
+        &mut router,
+        Addr::unchecked("user1"),
+        Uint128::new(100),
+        Direction::Bull,
+        &config.token_addr,
+        &prediction_market_addr,
+        Uint128::zero(),
+    );
+    execute_bet(
+        &mut router,
+        Addr::unchecked("user2"),
+        Uint128::new(200),
+        Direction::Bear,
+        &config.token_addr,
+        &prediction_market_addr,
+        Uint128::zero(),
+    );
+    execute_bet(
+        &mut router,
+        Addr::unchecked("user4"),
+        Uint128::new(100),
+        Direction::Bear,
+        &config.token_addr,
+        &prediction_market_addr,
+        Uint128::zero(),
+    );
+
+    let referral: ReferralResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::Referral {
+                address: Addr::unchecked("user3"),
+            },
+        )
+        .unwrap();
+    /* Held until the round is decided */
+    assert_eq!(referral.earnings, Uint128::zero());
+    assert_eq!(referral.pending, Uint128::new(1));
+    assert_eq!(referral.referred_count, 2);
+    let solvency: SolvencyResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Solvency {})
+        .unwrap();
+    assert_eq!(solvency.referral_earnings, Uint128::new(1));
+    assert_eq!(solvency.accumulated_fee, Uint128::new(2 + 4 + 2 - 1));
+    router
+        .execute_contract(
+            Addr::unchecked("user3"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::ClaimReferralEarnings {},
+            &[],
+        )
+        .unwrap_err();
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    update_price(&mut router, config.clone(), Uint128::new(2_000_000), &owner);
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    let referral: ReferralResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::Referral {
+                address: Addr::unchecked("user3"),
+            },
+        )
+        .unwrap();
+    assert_eq!(referral.earnings, Uint128::new(1));
+    assert_eq!(referral.pending, Uint128::zero());
+
+    let referral: ReferralResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::Referral {
+                address: Addr::unchecked("user1"),
+            },
+        )
+        .unwrap();
+    assert_eq!(referral.referrer, Some(Addr::unchecked("user3")));
+
+    router
+        .execute_contract(
+            Addr::unchecked("user3"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::ClaimReferralEarnings {},
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+    assert_eq!(
+        query_balance(&router, &config.token_addr, "user3"),
+        Uint128::new(1001)
+    );
+    router
+        .execute_contract(
+            Addr::unchecked("user3"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::ClaimReferralEarnings {},
+            &[],
+        )
+        .unwrap_err();
+}
+
+#[test]
+fn test_refund_returns_referral_share() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let prediction_market_addr = create_prediction_market(&mut router, &owner, default_config());
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+    router
+        .execute_contract(
+            Addr::unchecked("user2"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::RegisterReferrer {
+                referrer: "user1".to_string(),
+            },
+            &[],
+        )
+        .unwrap();
+
+    /* 8 fee, 2 of it for the referrer, and nobody on the other side */
+    execute_bet(
+        &mut router,
+        Addr::unchecked("user2"),
+        Uint128::new(400),
+        Direction::Bull,
+        &config.token_addr,
+        &prediction_market_addr,
+        Uint128::zero(),
+    );
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    router
+        .execute_contract(
+            Addr::unchecked("user2"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CollectWinnings {
+                player: None,
+                recipient: None,
+            },
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+    assert_eq!(
+        query_balance(&router, &config.token_addr, "user2"),
+        Uint128::new(1000)
+    );
+
+    let referral: ReferralResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::Referral {
+                address: Addr::unchecked("user1"),
+            },
+        )
+        .unwrap();
+    assert_eq!(referral.earnings, Uint128::zero());
+    assert_eq!(referral.pending, Uint128::zero());
+    router
+        .execute_contract(
+            Addr::unchecked("user1"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::ClaimReferralEarnings {},
+            &[],
+        )
+        .unwrap_err();
+}
+
+#[test]
+fn test_distribute_fees() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let staking_rewards_code_id = router.store_code(contract_staking_rewards());
+    let staking_rewards_addr = router
+        .instantiate_contract(
+            staking_rewards_code_id,
+            owner.clone(),
+            &Empty {},
+            &[],
+            "staking_rewards",
+            None,
+        )
+        .unwrap();
+    let prediction_market_addr = create_prediction_market(
+        &mut router,
+        &owner,
+        Config {
+            staking_rewards_addr: Some(staking_rewards_addr.clone()),
+            ..default_config()
+        },
+    );
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+
+    router
+        .execute_contract(
+            Addr::unchecked("keeper"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::DistributeFees {},
+            &[],
+        )
+        .unwrap_err();
+
+    /* 2% of 500 on each side */
+    execute_bet(
+        &mut router,
+        Addr::unchecked("user1"),
+        Uint128::new(500),
+        Direction::Bull,
+        &config.token_addr,
+        &prediction_market_addr,
+        Uint128::zero(),
+    );
+    execute_bet(
+        &mut router,
+        Addr::unchecked("user2"),
+        Uint128::new(500),
+        Direction::Bear,
+        &config.token_addr,
+        &prediction_market_addr,
+        Uint128::zero(),
+    );
+
+    /* Held back until the round is decided, it could still be refunded */
+    router
+        .execute_contract(
+            Addr::unchecked("keeper"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::DistributeFees {},
+            &[],
+        )
+        .unwrap_err();
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    update_price(&mut router, config.clone(), Uint128::new(2_000_000), &owner);
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    router
+        .execute_contract(
+            Addr::unchecked("keeper"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::DistributeFees {},
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+    assert_eq!(
+        query_balance(&router, &config.token_addr, staking_rewards_addr.as_str()),
+        Uint128::new(10)
+    );
+    assert_eq!(
+        query_balance(&router, &config.token_addr, "treasury"),
+        Uint128::new(6)
+    );
+    let token_info: cw20::TokenInfoResponse = router
+        .wrap()
+        .query_wasm_smart(config.token_addr.to_string(), &Cw20QueryMsg::TokenInfo {})
+        .unwrap();
+    assert_eq!(token_info.total_supply, Uint128::new(4000 - 4));
+
+    /* Shares have to add up to 100% */
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::UpdateConfig {
+                config: Box::new(Config {
+                    fee_distribution: FeeDistribution {
+                        staker_share: Uint128::new(5000u128),
+                        treasury_share: Uint128::new(5000u128),
+                        burn_share: Uint128::new(1u128),
+                    },
+                    ..config
+                }),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(err.root_cause().to_string().contains("add up to"));
+}
+
+fn query_fee_tier(router: &App, prediction_market_addr: &Addr, player: &str) -> FeeTierResponse {
+    router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::FeeTier {
+                player: Addr::unchecked(player),
+            },
+        )
+        .unwrap()
+}
+
+#[test]
+fn test_fee_tiers() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let staking_code_id = router.store_code(contract_staking());
+    let staking_addr = router
+        .instantiate_contract(
+            staking_code_id,
+            owner.clone(),
+            &Empty {},
+            &[],
+            "staking",
+            None,
+        )
+        .unwrap();
+    let prediction_market_addr = create_prediction_market(
+        &mut router,
+        &owner,
+        Config {
+            fee_tiers: vec![
+                FeeTier {
+                    min_volume: Uint128::new(500),
+                    min_staked: Uint128::zero(),
+                    gaming_fee: Uint128::new(100u128),
+                },
+                FeeTier {
+                    min_volume: Uint128::zero(),
+                    min_staked: Uint128::new(300),
+                    gaming_fee: Uint128::new(50u128),
+                },
+            ],
+            staking_addr: Some(staking_addr),
+            ..default_config()
+        },
+    );
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+
+    let fee_tier = query_fee_tier(&router, &prediction_market_addr, "user1");
+    assert_eq!(fee_tier.tier, None);
+    assert_eq!(fee_tier.gaming_fee, Uint128::new(200));
+
+    /* 2% until the volume reaches 500, 1% after */
+    for amount in [300, 300, 100] {
+        execute_bet(
+            &mut router,
+            Addr::unchecked("user1"),
+            Uint128::new(amount),
+            Direction::Bull,
+            &config.token_addr,
+            &prediction_market_addr,
+            Uint128::zero(),
+        );
+    }
+    let fee_tier = query_fee_tier(&router, &prediction_market_addr, "user1");
+    assert_eq!(fee_tier.tier, Some(0));
+    assert_eq!(fee_tier.rolling_volume, Uint128::new(700));
+    let solvency: SolvencyResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Solvency {})
+        .unwrap();
+    assert_eq!(solvency.accumulated_fee, Uint128::new(6 + 6 + 1));
+
+    /* Stakers get their tier without any volume */
+    let fee_tier = query_fee_tier(&router, &prediction_market_addr, "user2");
+    assert_eq!(fee_tier.tier, Some(1));
+    assert_eq!(fee_tier.gaming_fee, Uint128::new(50));
+    assert_eq!(fee_tier.staked_balance, Uint128::new(500));
+
+    /* Cancelled bets don't count, a switched bet keeps what is left after the penalty */
+    let user3 = Addr::unchecked("user3");
+    for _ in 0..2 {
+        execute_bet(
+            &mut router,
+            user3.clone(),
+            Uint128::new(300),
+            Direction::Bull,
+            &config.token_addr,
+            &prediction_market_addr,
+            Uint128::zero(),
+        );
+        router
+            .execute_contract(
+                user3.clone(),
+                prediction_market_addr.clone(),
+                &ExecuteMsg::CancelBet {
+                    round_id: Uint128::zero(),
+                },
+                &[],
+            )
+            .unwrap();
+        assert_solvent(&router, &prediction_market_addr);
+    }
+    let fee_tier = query_fee_tier(&router, &prediction_market_addr, "user3");
+    assert_eq!(fee_tier.tier, None);
+    assert!(fee_tier.rolling_volume.is_zero());
+
+    execute_bet(
+        &mut router,
+        user3.clone(),
+        Uint128::new(300),
+        Direction::Bull,
+        &config.token_addr,
+        &prediction_market_addr,
+        Uint128::zero(),
+    );
+    router
+        .execute_contract(
+            user3.clone(),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::SwitchDirection {
+                round_id: Uint128::zero(),
+            },
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+    /* 5% of the 294 left after the fee */
+    assert_eq!(
+        query_fee_tier(&router, &prediction_market_addr, "user3").rolling_volume,
+        Uint128::new(300 - 14)
+    );
+    router
+        .execute_contract(
+            user3,
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CancelBet {
+                round_id: Uint128::zero(),
+            },
+            &[],
+        )
+        .unwrap();
+    assert!(query_fee_tier(&router, &prediction_market_addr, "user3")
+        .rolling_volume
+        .is_zero());
+
+    /* Volume still counts in the next window, not in the one after */
+    router.update_block(|block| block.time = block.time.plus_seconds(3600));
+    assert_eq!(
+        query_fee_tier(&router, &prediction_market_addr, "user1").tier,
+        Some(0)
+    );
+    router.update_block(|block| block.time = block.time.plus_seconds(3600));
+    let fee_tier = query_fee_tier(&router, &prediction_market_addr, "user1");
+    assert_eq!(fee_tier.tier, None);
+    assert!(fee_tier.rolling_volume.is_zero());
+}
+
+#[test]
+fn test_fee_on_winnings() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let prediction_market_addr = create_prediction_market(
+        &mut router,
+        &owner,
+        Config {
+            fee_mode: FeeMode::OnWinnings,
+            fee_tiers: vec![FeeTier {
+                min_volume: Uint128::zero(),
+                min_staked: Uint128::zero(),
+                gaming_fee: Uint128::new(50u128),
+            }],
+            ..default_config()
+        },
+    );
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+
+    /* Referrals and fee tiers only apply to fees taken from stakes */
+    router
+        .execute_contract(
+            Addr::unchecked("user1"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::RegisterReferrer {
+                referrer: "user4".to_string(),
+            },
+            &[],
+        )
+        .unwrap();
+    execute_bet(
+        &mut router,
+        Addr::unchecked("user1"),
+        Uint128::new(100),
+        Direction::Bull,
+        &config.token_addr,
+        &prediction_market_addr,
+        Uint128::zero(),
+    );
+    execute_bet(
+        &mut router,
+        Addr::unchecked("user2"),
+        Uint128::new(100),
+        Direction::Bear,
+        &config.token_addr,
+        &prediction_market_addr,
+        Uint128::zero(),
+    );
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    /* One-sided round, nobody can win it */
+    execute_bet(
+        &mut router,
+        Addr::unchecked("user3"),
+        Uint128::new(100),
+        Direction::Bull,
+        &config.token_addr,
+        &prediction_market_addr,
+        Uint128::new(1),
+    );
+    let solvency: SolvencyResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Solvency {})
+        .unwrap();
+    assert!(solvency.accumulated_fee.is_zero());
+    assert_eq!(solvency.open_stakes, Uint128::new(300));
+
+    update_price(&mut router, config.clone(), Uint128::new(2_000_000), &owner);
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    /* 2% of the 200 pool, the tier's 0.5% doesn't apply */
+    let round: RoundResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::FinishedRound {
+                round_id: Uint128::zero(),
+            },
+        )
+        .unwrap();
+    assert_eq!(round.rake, Uint128::new(4));
+    let round: RoundResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::FinishedRound {
+                round_id: Uint128::new(1),
+            },
+        )
+        .unwrap();
+    assert!(round.rake.is_zero());
+
+    for user in ["user1", "user3"] {
+        router
+            .execute_contract(
+                Addr::unchecked(user),
+                prediction_market_addr.clone(),
+                &ExecuteMsg::CollectWinnings {
+                    player: None,
+                    recipient: None,
+                },
+                &[],
+            )
+            .unwrap();
+    }
+    assert_solvent(&router, &prediction_market_addr);
+    assert_eq!(
+        query_balance(&router, &config.token_addr, "user1"),
+        Uint128::new(900 + 196)
+    );
+    assert_eq!(
+        query_balance(&router, &config.token_addr, "user3"),
+        Uint128::new(1000)
+    );
+    let solvency: SolvencyResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Solvency {})
+        .unwrap();
+    assert_eq!(solvency.accumulated_fee, Uint128::new(4));
+
+    let referral: ReferralResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::Referral {
+                address: Addr::unchecked("user4"),
+            },
+        )
+        .unwrap();
+    assert!(referral.earnings.is_zero());
+    assert!(referral.pending.is_zero());
+}
+
+#[test]
+fn test_void_round_refunds_fee() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let prediction_market_addr = create_prediction_market(&mut router, &owner, default_config());
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+
+    /* One-sided round, and a cancelled bet that keeps paying its fee */
+    execute_bet(
+        &mut router,
+        Addr::unchecked("user1"),
+        Uint128::new(100),
+        Direction::Bull,
+        &config.token_addr,
+        &prediction_market_addr,
+        Uint128::zero(),
+    );
+    execute_bet(
+        &mut router,
+        Addr::unchecked("user2"),
+        Uint128::new(100),
+        Direction::Bear,
+        &config.token_addr,
+        &prediction_market_addr,
+        Uint128::zero(),
+    );
+    router
+        .execute_contract(
+            Addr::unchecked("user2"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CancelBet {
+                round_id: Uint128::zero(),
+            },
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+
+    let bets: MyGameResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::MyGameList {
+                player: Addr::unchecked("user1"),
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+    assert_eq!(bets.my_game_list[0].gross_amount, Uint128::new(100));
+    assert_eq!(bets.my_game_list[0].fee, Uint128::new(2));
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    router
+        .execute_contract(
+            Addr::unchecked("user1"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CollectWinnings {
+                player: None,
+                recipient: None,
+            },
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+    assert_eq!(
+        query_balance(&router, &config.token_addr, "user1"),
+        Uint128::new(1000)
+    );
+
+    /* Only the cancelled bet's fee and penalty are left */
+    let solvency: SolvencyResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Solvency {})
+        .unwrap();
+    assert_eq!(solvency.accumulated_fee, Uint128::new(2 + 4));
+}
+
+#[test]
+fn test_oracle_sources() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+
+    let pyth_code_id = router.store_code(contract_pyth_oracle());
+    let pyth_addr = router
+        .instantiate_contract(pyth_code_id, owner.clone(), &Empty {}, &[], "pyth", None)
+        .unwrap();
+    let band_code_id = router.store_code(contract_band_oracle());
+    let band_addr = router
+        .instantiate_contract(band_code_id, owner.clone(), &Empty {}, &[], "band", None)
+        .unwrap();
+
+    /* Both report 2.0 and then 2.5, in their own format */
+    for (oracle, next_price) in [
+        (
+            OracleSource::Pyth {
+                addr: pyth_addr,
+                price_feed_id: "btc_usd".to_string(),
+            },
+            Uint128::new(250_000_000u128),
+        ),
+        (
+            OracleSource::Band {
+                addr: band_addr,
+                base_symbol: "BTC".to_string(),
+                quote_symbol: "USD".to_string(),
+            },
+            Uint128::new(2_500_000_000_000_000_000u128),
+        ),
+    ] {
+        let prediction_market_addr = create_prediction_market(
+            &mut router,
+            &owner,
+            Config {
+                oracles: vec![oracle],
+                ..default_config()
+            },
+        );
+        let config: ConfigResponse = router
+            .wrap()
+            .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+            .unwrap();
+
+        start_next_round(&mut router, &prediction_market_addr, &owner);
+        start_next_round(&mut router, &prediction_market_addr, &owner);
+        update_price(&mut router, config.clone(), next_price, &owner);
+        start_next_round(&mut router, &prediction_market_addr, &owner);
+
+        let round: RoundResponse = router
+            .wrap()
+            .query_wasm_smart(
+                prediction_market_addr.to_string(),
+                &QueryMsg::FinishedRound {
+                    round_id: Uint128::zero(),
+                },
+            )
+            .unwrap();
+        assert_eq!(round.open_price, Uint128::new(200_000_000));
+        assert_eq!(round.close_price, Uint128::new(250_000_000));
+        assert_eq!(round.winner, Some(Direction::Bull));
+    }
+}
+
+#[test]
+fn test_oracle_absurd_decimals() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let second_oracle = init_fast_oracle_contract(&mut router, &owner);
+
+    /* 10^200 doesn't fit anywhere, the oracle is skipped instead of panicking */
+    let prediction_market_addr = create_prediction_market(
+        &mut router,
+        &owner,
+        Config {
+            oracles: vec![
+                OracleSource::FastOracle {
+                    addr: Addr::unchecked("fast_oracle"),
+                    decimals: 6,
+                },
+                OracleSource::FastOracle {
+                    addr: second_oracle,
+                    decimals: 200,
+                },
+            ],
+            ..default_config()
+        },
+    );
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    let health: OracleHealthResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::OracleHealth {},
+        )
+        .unwrap();
+    assert_eq!(health.oracles[0].price, Some(Uint128::new(100_000_000)));
+    assert!(health.oracles[1].error.is_some());
+    let status: StatusResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Status {})
+        .unwrap();
+    assert_eq!(
+        status.live_round.unwrap().open_price,
+        Uint128::new(100_000_000)
+    );
+}
+
+#[test]
+fn test_wasmswap_oracle() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let pool_code_id = router.store_code(contract_wasmswap_pool());
+
+    /* Spot jumps straight to 2.0, the TWAP only gets halfway along the ramp there */
+    for (twap_window_seconds, close_price) in [
+        (None, Uint128::new(200_000_000)),
+        (Some(1200), Uint128::new(125_000_000)),
+    ] {
+        let pool_addr = router
+            .instantiate_contract(pool_code_id, owner.clone(), &Empty {}, &[], "pool", None)
+            .unwrap();
+        let prediction_market_addr = create_prediction_market(
+            &mut router,
+            &owner,
+            Config {
+                oracles: vec![OracleSource::Wasmswap {
+                    addr: pool_addr,
+                    twap_window_seconds,
+                }],
+                ..default_config()
+            },
+        );
+        let config: ConfigResponse = router
+            .wrap()
+            .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+            .unwrap();
+
+        start_next_round(&mut router, &prediction_market_addr, &owner);
+        start_next_round(&mut router, &prediction_market_addr, &owner);
+        update_price(&mut router, config.clone(), Uint128::new(2_000_000), &owner);
+        start_next_round(&mut router, &prediction_market_addr, &owner);
+
+        let round: RoundResponse = router
+            .wrap()
+            .query_wasm_smart(
+                prediction_market_addr.to_string(),
+                &QueryMsg::FinishedRound {
+                    round_id: Uint128::zero(),
+                },
+            )
+            .unwrap();
+        assert_eq!(round.open_price, Uint128::new(100_000_000));
+        assert_eq!(round.close_price, close_price);
+        assert_eq!(round.winner, Some(Direction::Bull));
+    }
+}
+
+#[test]
+fn test_twap_per_pool() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let pool_code_id = router.store_code(contract_wasmswap_pool());
+    let pools: Vec<Addr> = (0..2)
+        .map(|_| {
+            router
+                .instantiate_contract(pool_code_id, owner.clone(), &Empty {}, &[], "pool", None)
+                .unwrap()
+        })
+        .collect();
+    router
+        .execute_contract(
+            owner.clone(),
+            pools[1].clone(),
+            &FastOracleExecuteMsg::Update {
+                price: Uint128::new(3_000_000),
+            },
+            &[],
+        )
+        .unwrap();
+
+    let prediction_market_addr = create_prediction_market(
+        &mut router,
+        &owner,
+        Config {
+            oracles: pools
+                .iter()
+                .map(|pool| OracleSource::Wasmswap {
+                    addr: pool.clone(),
+                    twap_window_seconds: Some(1200),
+                })
+                .collect(),
+            oracle_quorum: 2,
+            max_oracle_deviation: Uint128::new(10_000u128),
+            ..default_config()
+        },
+    );
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    /* Both pools are sampled and averaged on their own */
+    let health: OracleHealthResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::OracleHealth {},
+        )
+        .unwrap();
+    assert_eq!(health.oracles[0].price, Some(Uint128::new(100_000_000)));
+    assert_eq!(health.oracles[1].price, Some(Uint128::new(300_000_000)));
+
+    let status: StatusResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Status {})
+        .unwrap();
+    assert_eq!(
+        status.live_round.unwrap().open_price,
+        Uint128::new(200_000_000)
+    );
+}
+
+#[test]
+fn test_twap_dead_pool() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let pool_code_id = router.store_code(contract_wasmswap_pool());
+    let pool_addr = router
+        .instantiate_contract(pool_code_id, owner.clone(), &Empty {}, &[], "pool", None)
+        .unwrap();
+    router
+        .execute_contract(
+            owner.clone(),
+            pool_addr.clone(),
+            &FastOracleExecuteMsg::Update {
+                price: Uint128::new(3_000_000),
+            },
+            &[],
+        )
+        .unwrap();
+    let fast_oracle_addr = init_fast_oracle_contract(&mut router, &owner);
+
+    let prediction_market_addr = create_prediction_market(
+        &mut router,
+        &owner,
+        Config {
+            oracles: vec![OracleSource::Wasmswap {
+                addr: pool_addr.clone(),
+                twap_window_seconds: Some(1200),
+            }],
+            fallback_oracles: vec![OracleSource::FastOracle {
+                addr: fast_oracle_addr.clone(),
+                decimals: 6,
+            }],
+            ..default_config()
+        },
+    );
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    let status: StatusResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Status {})
+        .unwrap();
+    assert_eq!(
+        status.live_round.unwrap().open_price,
+        Uint128::new(300_000_000)
+    );
+
+    /* Two samples are stored, the pool's last TWAP must not outlive it */
+    router
+        .execute_contract(
+            owner.clone(),
+            pool_addr,
+            &FastOracleExecuteMsg::Update {
+                price: Uint128::zero(),
+            },
+            &[],
+        )
+        .unwrap();
+    router.update_block(|block| {
+        block.time = block.time.plus_seconds(5);
+        block.height += 1;
+    });
+    let health: OracleHealthResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::OracleHealth {},
+        )
+        .unwrap();
+    assert_eq!(health.oracles[0].price, None);
+    assert!(!health.quorum_met);
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    let status: StatusResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Status {})
+        .unwrap();
+    let live_round = status.live_round.unwrap();
+    assert_eq!(live_round.open_price, Uint128::new(100_000_000));
+    assert_eq!(
+        live_round.open_observation.source,
+        PriceSource::Fallback {
+            index: 0,
+            addr: fast_oracle_addr,
+        }
+    );
+}
+
+#[test]
+fn test_oracle_median() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let second_oracle = init_fast_oracle_contract(&mut router, &owner);
+    let third_oracle = init_fast_oracle_contract(&mut router, &owner);
+
+    /* The last oracle never answers, a fast oracle doesn't speak Pyth */
+    let prediction_market_addr = create_prediction_market(
+        &mut router,
+        &owner,
+        Config {
+            oracles: vec![
+                OracleSource::FastOracle {
+                    addr: Addr::unchecked("fast_oracle"),
+                    decimals: 6,
+                },
+                OracleSource::FastOracle {
+                    addr: second_oracle.clone(),
+                    decimals: 6,
+                },
+                OracleSource::FastOracle {
+                    addr: third_oracle.clone(),
+                    decimals: 6,
+                },
+                OracleSource::Pyth {
+                    addr: third_oracle.clone(),
+                    price_feed_id: "btc_usd".to_string(),
+                },
+            ],
+            oracle_quorum: 3,
+            ..default_config()
+        },
+    );
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+    let update_prices = |router: &mut App, prices: [u128; 3]| {
+        for (oracle, price) in [
+            config.oracles[0].addr().clone(),
+            second_oracle.clone(),
+            third_oracle.clone(),
+        ]
+        .iter()
+        .zip(prices)
+        {
+            router
+                .execute_contract(
+                    owner.clone(),
+                    oracle.clone(),
+                    &FastOracleExecuteMsg::Update {
+                        price: Uint128::new(price),
+                    },
+                    &[],
+                )
+                .unwrap();
+        }
+    };
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    for (user, direction) in [("user1", Direction::Bull), ("user2", Direction::Bear)] {
+        execute_bet(
+            &mut router,
+            Addr::unchecked(user),
+            Uint128::new(100),
+            direction,
+            &config.token_addr,
+            &prediction_market_addr,
+            Uint128::zero(),
+        );
+    }
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    for (user, direction) in [("user3", Direction::Bull), ("user4", Direction::Bear)] {
+        execute_bet(
+            &mut router,
+            Addr::unchecked(user),
+            Uint128::new(100),
+            direction,
+            &config.token_addr,
+            &prediction_market_addr,
+            Uint128::from(1u128),
+        );
+    }
+
+    /* Close enough together, the median decides */
+    update_prices(&mut router, [1_020_000, 1_030_000, 1_040_000]);
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    let round: RoundResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::FinishedRound {
+                round_id: Uint128::zero(),
+            },
+        )
+        .unwrap();
+    assert_eq!(round.close_price, Uint128::new(103_000_000));
+    assert_eq!(round.winner, Some(Direction::Bull));
+    assert!(!round.cancelled);
+
+    /* One oracle is way off, the round is cancelled and refunded */
+    update_prices(&mut router, [1_050_000, 1_030_000, 2_000_000]);
+    router.update_block(|block| {
+        block.time = block.time.plus_seconds(600);
+        block.height += 1;
+    });
+    let res = router
+        .execute_contract(
+            owner.clone(),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CloseRound {},
+            &[],
+        )
+        .unwrap();
+    let observed = format!(
+        "{}:105000000,{}:103000000,{}:200000000,{}:none",
+        config.oracles[0].addr(),
+        second_oracle,
+        third_oracle,
+        third_oracle
+    );
+    assert!(res.events.iter().any(|event| event
+        .attributes
+        .iter()
+        .any(|attr| attr.key == "close_oracle_prices" && attr.value == observed)));
+
+    let round: RoundResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::FinishedRound {
+                round_id: Uint128::from(1u128),
+            },
+        )
+        .unwrap();
+    assert_eq!(round.close_price, Uint128::new(105_000_000));
+    assert_eq!(round.winner, None);
+    assert_eq!(round.tie_policy, Some(TiePolicy::Refund));
+    assert!(round.cancelled);
+
+    for user in ["user3", "user4"] {
+        router
+            .execute_contract(
+                Addr::unchecked(user),
+                prediction_market_addr.clone(),
+                &ExecuteMsg::CollectWinnings {
+                    player: None,
+                    recipient: None,
+                },
+                &[],
+            )
+            .unwrap();
+        assert_eq!(
+            query_balance(&router, &config.token_addr, user),
+            Uint128::new(1000)
+        );
+    }
+    assert_solvent(&router, &prediction_market_addr);
+
+    /* Without a quorum no price is used at all */
+    router
+        .execute_contract(
+            owner.clone(),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::UpdateConfig {
+                config: Box::new(Config {
+                    oracle_quorum: 4,
+                    ..config.clone()
+                }),
+            },
+            &[],
+        )
+        .unwrap();
+    router.update_block(|block| {
+        block.time = block.time.plus_seconds(600);
+        block.height += 1;
+    });
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CloseRound {},
+            &[],
+        )
+        .unwrap_err();
+    assert!(err.root_cause().to_string().contains("3 of 4 oracles"));
+
+    /* A quorum larger than the oracles can never be met */
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::UpdateConfig {
+                config: Box::new(Config {
+                    oracle_quorum: 5,
+                    ..config
+                }),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(err.root_cause().to_string().contains("oracle quorum"));
+}
+
+#[test]
+fn test_fallback_oracle() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let backup_oracle = init_fast_oracle_contract(&mut router, &owner);
+
+    /* The first fallback never answers, a fast oracle doesn't speak Pyth */
+    let prediction_market_addr = create_prediction_market(
+        &mut router,
+        &owner,
+        Config {
+            fallback_oracles: vec![
+                OracleSource::Pyth {
+                    addr: backup_oracle.clone(),
+                    price_feed_id: "btc_usd".to_string(),
+                },
+                OracleSource::FastOracle {
+                    addr: backup_oracle.clone(),
+                    decimals: 6,
+                },
+            ],
+            ..default_config()
+        },
+    );
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    /* A fallback pool that can't be sampled doesn't stop rounds from closing */
+    router
+        .execute_contract(
+            owner.clone(),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::UpdateConfig {
+                config: Box::new(Config {
+                    fallback_oracles: [
+                        config.fallback_oracles.clone(),
+                        vec![OracleSource::Wasmswap {
+                            addr: backup_oracle.clone(),
+                            twap_window_seconds: Some(1200),
+                        }],
+                    ]
+                    .concat(),
+                    ..config.clone()
+                }),
+            },
+            &[],
+        )
+        .unwrap();
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+
+    /* The primary goes down */
+    router
+        .execute_contract(
+            owner.clone(),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::UpdateConfig {
+                config: Box::new(Config {
+                    oracles: vec![OracleSource::Pyth {
+                        addr: config.oracles[0].addr().clone(),
+                        price_feed_id: "btc_usd".to_string(),
+                    }],
+                    ..config.clone()
+                }),
+            },
+            &[],
+        )
+        .unwrap();
+    router
+        .execute_contract(
+            owner.clone(),
+            backup_oracle.clone(),
+            &FastOracleExecuteMsg::Update {
+                price: Uint128::new(1_100_000),
+            },
+            &[],
+        )
+        .unwrap();
+
+    let health: OracleHealthResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::OracleHealth {},
+        )
+        .unwrap();
+    assert!(!health.quorum_met);
+    assert_eq!(health.oracles[0].price, None);
+    assert!(health.oracles[0].error.is_some());
+    assert!(health.fallback_oracles[0].error.is_some());
+    assert_eq!(
+        health.fallback_oracles[1].price,
+        Some(Uint128::new(110_000_000))
+    );
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+
+    let fallback = PriceSource::Fallback {
+        index: 1,
+        addr: backup_oracle,
+    };
+    let round: RoundResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::FinishedRound {
+                round_id: Uint128::zero(),
+            },
+        )
+        .unwrap();
+    assert_eq!(round.open_observation.source, PriceSource::Primary);
+    assert_eq!(round.close_observation.source, fallback);
+    assert_eq!(round.close_price, Uint128::new(110_000_000));
+    assert_eq!(round.winner, Some(Direction::Bull));
+
+    let status: StatusResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Status {})
+        .unwrap();
+    assert_eq!(status.live_round.unwrap().open_observation.source, fallback);
+}
+
+#[test]
+fn test_reference_oracle() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let oracle_addr = init_fast_oracle_contract(&mut router, &owner);
+    let feeder = Addr::unchecked("feeder");
+    let update = |router: &mut App, sender: &Addr, price: u128| {
+        router.execute_contract(
+            sender.clone(),
+            oracle_addr.clone(),
+            &FastOracleExecuteMsg::Update {
+                price: Uint128::new(price),
+            },
+            &[],
+        )
+    };
+
+    /* Only the owner and feeders can feed */
+    update(&mut router, &feeder, 1_100_000).unwrap_err();
+    router
+        .execute_contract(
+            owner.clone(),
+            oracle_addr.clone(),
+            &FastOracleExecuteMsg::AddFeeder {
+                feeder: feeder.clone(),
+            },
+            &[],
+        )
+        .unwrap();
+    router.update_block(|block| {
+        block.time = block.time.plus_seconds(60);
+        block.height += 1;
+    });
+    update(&mut router, &feeder, 1_100_000).unwrap();
+
+    let price: Uint128 = router
+        .wrap()
+        .query_wasm_smart(oracle_addr.to_string(), &FastOracleQueryMsg::Price {})
+        .unwrap();
+    assert_eq!(price, Uint128::new(1_100_000));
+    let data: FastOraclePriceData = router
+        .wrap()
+        .query_wasm_smart(oracle_addr.to_string(), &FastOracleQueryMsg::PriceData {})
+        .unwrap();
+    assert_eq!(data.id, 1);
+    assert_eq!(data.feeder, feeder);
+    assert_eq!(data.updated_at, router.block_info().time);
+    assert_eq!(data.height, router.block_info().height);
+
+    let history: FastOracleHistoryResponse = router
+        .wrap()
+        .query_wasm_smart(
+            oracle_addr.to_string(),
+            &FastOracleQueryMsg::History {
+                start_after: Some(1),
+                limit: None,
+            },
+        )
+        .unwrap();
+    assert_eq!(history.prices.len(), 1);
+    assert_eq!(history.prices[0].price, Uint128::new(1_000_000));
+    assert_eq!(history.prices[0].feeder, owner);
+
+    /* A new owner takes over feeder management */
+    router
+        .execute_contract(
+            owner.clone(),
+            oracle_addr.clone(),
+            &FastOracleExecuteMsg::Owner {
+                owner: Addr::unchecked("new_owner"),
+            },
+            &[],
+        )
+        .unwrap();
+    router
+        .execute_contract(
+            owner.clone(),
+            oracle_addr.clone(),
+            &FastOracleExecuteMsg::RemoveFeeder {
+                feeder: feeder.clone(),
+            },
+            &[],
+        )
+        .unwrap_err();
+    router
+        .execute_contract(
+            Addr::unchecked("new_owner"),
+            oracle_addr.clone(),
+            &FastOracleExecuteMsg::RemoveFeeder {
+                feeder: feeder.clone(),
+            },
+            &[],
+        )
+        .unwrap();
+    update(&mut router, &feeder, 1_200_000).unwrap_err();
+    update(&mut router, &owner, 1_200_000).unwrap_err();
+
+    let config: FastOracleConfigResponse = router
+        .wrap()
+        .query_wasm_smart(oracle_addr.to_string(), &FastOracleQueryMsg::Config {})
+        .unwrap();
+    assert_eq!(config.owner, Addr::unchecked("new_owner"));
+    assert!(config.feeders.is_empty());
+}
+
+#[test]
+fn test_price_observations() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    let pyth_code_id = router.store_code(contract_pyth_oracle());
+    let pyth_addr = router
+        .instantiate_contract(pyth_code_id, owner.clone(), &Empty {}, &[], "pyth", None)
+        .unwrap();
+    let bare_oracle_code_id = router.store_code(contract_bare_fast_oracle());
+    let bare_oracle_addr = router
+        .instantiate_contract(
+            bare_oracle_code_id,
+            owner.clone(),
+            &Empty {},
+            &[],
+            "bare_fast_oracle",
+            None,
+        )
+        .unwrap();
+
+    let prediction_market_addr = create_prediction_market(
+        &mut router,
+        &owner,
+        Config {
+            oracles: vec![
+                OracleSource::FastOracle {
+                    addr: Addr::unchecked("fast_oracle"),
+                    decimals: 6,
+                },
+                OracleSource::Pyth {
+                    addr: pyth_addr.clone(),
+                    price_feed_id: "btc_usd".to_string(),
+                },
+            ],
+            oracle_quorum: 2,
+            fallback_oracles: vec![OracleSource::FastOracle {
+                addr: bare_oracle_addr,
+                decimals: 6,
+            }],
+            ..default_config()
+        },
+    );
+    router
+        .execute_contract(
+            owner.clone(),
+            pyth_addr,
+            &FastOracleExecuteMsg::Update {
+                price: Uint128::new(100_000_000),
+            },
+            &[],
+        )
+        .unwrap();
+
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+    let fast_oracle_data: FastOraclePriceData = router
+        .wrap()
+        .query_wasm_smart(
+            config.oracles[0].addr().to_string(),
+            &FastOracleQueryMsg::PriceData {},
+        )
+        .unwrap();
+
+    /* The fast oracle says when it was fed, an oracle that only answers Price can't */
+    let health: OracleHealthResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::OracleHealth {},
+        )
+        .unwrap();
+    assert_eq!(
+        health.oracles[0].publish_time,
+        Some(fast_oracle_data.updated_at)
+    );
+    assert_eq!(
+        health.fallback_oracles[0].price,
+        Some(Uint128::new(100_000_000))
+    );
+    assert_eq!(health.fallback_oracles[0].publish_time, None);
+
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    let locked = router.block_info();
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    let closed = router.block_info();
+
+    /* Only Pyth has a confidence, the fast oracle's older feed is the publish time */
+    let round: RoundResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::FinishedRound {
+                round_id: Uint128::zero(),
+            },
+        )
+        .unwrap();
+    for (observation, block) in [
+        (&round.open_observation, &locked),
+        (&round.close_observation, &closed),
+    ] {
+        assert_eq!(
+            observation,
+            &PriceObservation {
+                source: PriceSource::Primary,
+                publish_time: Some(fast_oracle_data.updated_at),
+                conf: Uint128::new(50_000),
+                block_height: block.height,
+                block_time: block.time,
+            }
+        );
+    }
+
+    let status: StatusResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Status {})
+        .unwrap();
+    assert_eq!(
+        status.live_round.unwrap().open_observation,
+        round.close_observation
+    );
+}
+
+/* The first version's rounds and bets, before fees, rollovers and observations were kept */
+#[cw_serde]
+struct LegacyNextRound {
+    id: Uint128,
+    bid_time: Timestamp,
+    open_time: Timestamp,
+    close_time: Timestamp,
+    bull_amount: Uint128,
+    bear_amount: Uint128,
+}
+
+#[cw_serde]
+struct LegacyLiveRound {
+    id: Uint128,
+    bid_time: Timestamp,
+    open_time: Timestamp,
+    close_time: Timestamp,
+    open_price: Uint128,
+    bull_amount: Uint128,
+    bear_amount: Uint128,
+}
+
+#[cw_serde]
+struct LegacyFinishedRound {
+    id: Uint128,
+    bid_time: Timestamp,
+    open_time: Timestamp,
+    close_time: Timestamp,
+    open_price: Uint128,
+    close_price: Uint128,
+    winner: Option<Direction>,
+    bull_amount: Uint128,
+    bear_amount: Uint128,
+}
+
+#[cw_serde]
+struct LegacyBetInfo {
+    player: Addr,
+    round_id: Uint128,
+    amount: Uint128,
+    direction: Direction,
+}
+
+/* Stands in for a deployment of the first version: round 0 won by the bulls, round 1 with
+ * nobody on the bear side, round 2 live and round 3 taking bets */
+pub fn contract_legacy_price_prediction() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(
+        |_, _, _, _: Empty| -> StdResult<Response> { Ok(Response::default()) },
+        |deps, _, _, config: LegacyConfig| -> StdResult<Response> {
+            cw2::set_contract_version(deps.storage, "price_prediction", "0.1.0")?;
+            LEGACY_CONFIG.save(deps.storage, &config)?;
+            Item::<bool>::new("is_haulted").save(deps.storage, &false)?;
+            Item::<u128>::new("next_round_id").save(deps.storage, &4)?;
+            Item::<u128>::new("accumulated_fee").save(deps.storage, &6)?;
+            Item::<Uint128>::new("total_volume").save(deps.storage, &Uint128::new(280))?;
+
+            let rounds: Map<u128, LegacyFinishedRound> = Map::new("rounds");
+            for (id, winner, bull_amount, bear_amount) in [
+                (0u64, Direction::Bull, 100u128, 100u128),
+                (1, Direction::Bear, 50, 0),
+            ] {
+                rounds.save(
+                    deps.storage,
+                    id.into(),
+                    &LegacyFinishedRound {
+                        id: Uint128::from(id),
+                        bid_time: Timestamp::from_seconds(id * 600),
+                        open_time: Timestamp::from_seconds(id * 600 + 600),
+                        close_time: Timestamp::from_seconds(id * 600 + 1200),
+                        open_price: Uint128::new(1_000_000),
+                        close_price: Uint128::new(2_000_000),
+                        winner: Some(winner),
+                        bull_amount: Uint128::new(bull_amount),
+                        bear_amount: Uint128::new(bear_amount),
+                    },
+                )?;
+            }
+            Item::new("live_round").save(
+                deps.storage,
+                &LegacyLiveRound {
+                    id: Uint128::new(2),
+                    bid_time: Timestamp::from_seconds(1200),
+                    open_time: Timestamp::from_seconds(1800),
+                    close_time: Timestamp::from_seconds(2400),
+                    open_price: Uint128::new(1_000_000),
+                    bull_amount: Uint128::new(30),
+                    bear_amount: Uint128::zero(),
+                },
+            )?;
+            Item::new("next_round").save(
+                deps.storage,
+                &LegacyNextRound {
+                    id: Uint128::new(3),
+                    bid_time: Timestamp::from_seconds(1800),
+                    open_time: Timestamp::from_seconds(2400),
+                    close_time: Timestamp::from_seconds(3000),
+                    bull_amount: Uint128::zero(),
+                    bear_amount: Uint128::zero(),
+                },
+            )?;
+
+            /* The index only depends on the player, the stored bet is swapped for the old one */
+            let bets: Map<(u128, Addr), LegacyBetInfo> = Map::new("bet_info");
+            for (player, round_id, amount, direction) in [
+                ("user1", 0u128, 100u128, Direction::Bull),
+                ("user2", 0, 100, Direction::Bear),
+                ("user1", 1, 50, Direction::Bull),
+                ("user2", 2, 30, Direction::Bull),
+            ] {
+                let player = Addr::unchecked(player);
+                let bet = LegacyBetInfo {
+                    player: player.clone(),
+                    round_id: Uint128::new(round_id),
+                    amount: Uint128::new(amount),
+                    direction,
+                };
+                bet_info_storage().save(
+                    deps.storage,
+                    bet_info_key(round_id, &player),
+                    &BetInfo {
+                        player: bet.player.clone(),
+                        round_id: bet.round_id,
+                        amount: bet.amount,
+                        gross_amount: bet.amount,
+                        fee: Uint128::zero(),
+                        referral_fee: Uint128::zero(),
+                        direction: bet.direction.clone(),
+                        from_balance: false,
+                    },
+                )?;
+                bets.save(deps.storage, (round_id, player), &bet)?;
+            }
+            Ok(Response::default())
+        },
+        |_, _, _: Empty| -> StdResult<Binary> { to_binary(&Empty {}) },
+    );
+    Box::new(contract)
+}
+
+#[test]
+fn test_migrate_legacy_layout() {
+    let mut router = mock_app();
+    let owner = Addr::unchecked("owner");
+    router.set_block(BlockInfo {
+        height: 0,
+        time: Timestamp::from_seconds(2100),
+        chain_id: "testing".to_string(),
+    });
+
+    let fast_oracle_addr = init_fast_oracle_contract(&mut router, &owner);
+    let token_addr = init_cw20_contract(&mut router, &owner);
+    let legacy_code_id = router.store_code(contract_legacy_price_prediction());
+    let prediction_market_addr = router
+        .instantiate_contract(
+            legacy_code_id,
+            owner.clone(),
+            &LegacyConfig {
+                next_round_seconds: Uint128::new(600),
+                fast_oracle_addr: fast_oracle_addr.clone(),
+                minimum_bet: Uint128::new(1),
+                burn_fee: Uint128::new(100),
+                gaming_fee: Uint128::new(200),
+                token_addr: token_addr.clone(),
+            },
+            &[],
+            "prediction_market",
+            Some(owner.to_string()),
+        )
+        .unwrap();
+    /* The stakes and the fees the old contract holds */
+    router
+        .execute_contract(
+            Addr::unchecked("user3"),
+            token_addr.clone(),
+            &Cw20ExecuteMsg::Transfer {
+                recipient: prediction_market_addr.to_string(),
+                amount: Uint128::new(286),
+            },
+            &[],
+        )
+        .unwrap();
+
+    let code_id = router.store_code(contract_price_prediction());
+    router
+        .migrate_contract(
+            owner.clone(),
+            prediction_market_addr.clone(),
+            &MigrateMsg {},
+            code_id,
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(prediction_market_addr.to_string(), &QueryMsg::Config {})
+        .unwrap();
+    assert_eq!(
+        config.oracles,
+        vec![OracleSource::FastOracle {
+            addr: fast_oracle_addr,
+            decimals: 8,
+        }]
+    );
+    assert_eq!(config.gaming_fee, Uint128::new(200));
+    assert_eq!(config.treasury_addr, owner);
+    assert_eq!(config.tie_policy, TiePolicy::Refund);
+
+    /* Bets still can't be cancelled */
+    router
+        .execute_contract(
+            Addr::unchecked("user2"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CancelBet {
+                round_id: Uint128::new(3),
+            },
+            &[],
+        )
+        .unwrap_err();
+
+    /* Round 0 pays the whole pool to the bulls, round 1 refunds the old way */
+    router
+        .execute_contract(
+            Addr::unchecked("user1"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CollectWinnings {
+                player: None,
+                recipient: None,
+            },
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+    assert_eq!(
+        query_balance(&router, &token_addr, "user1"),
+        Uint128::new(1000 + 250)
+    );
+
+    /* The live round closes the current way, one-sided it's refunded */
+    start_next_round(&mut router, &prediction_market_addr, &owner);
+    let round: RoundResponse = router
+        .wrap()
+        .query_wasm_smart(
+            prediction_market_addr.to_string(),
+            &QueryMsg::FinishedRound {
+                round_id: Uint128::new(2),
+            },
+        )
+        .unwrap();
+    assert_eq!(round.tie_policy, Some(TiePolicy::Refund));
+    router
+        .execute_contract(
+            Addr::unchecked("user2"),
+            prediction_market_addr.clone(),
+            &ExecuteMsg::CollectWinnings {
+                player: None,
+                recipient: None,
+            },
+            &[],
+        )
+        .unwrap();
+    assert_solvent(&router, &prediction_market_addr);
+    assert_eq!(
+        query_balance(&router, &token_addr, "user2"),
+        Uint128::new(1000 + 30)
+    );
+    assert_eq!(
+        query_balance(&router, &token_addr, owner.as_str()),
+        Uint128::zero()
+    );
+}